@@ -56,5 +56,32 @@ fn read_gdp_csv3_csv(bench: &mut Bencher) {
     });
 }
 
-benchmark_group!(benches, read_gdp_csv, read_gdp_csv2, read_gdp_csv3, read_gdp_csv3_csv);
+fn fill_gdp_csv3_csv(bench: &mut Bencher) {
+    let mut out = [0u32; 256];
+    bench.iter(|| {
+        let mut bf = bufchr::BufchrCSV::new(CSV_HAYSTACK, b',');
+        while bf.fill_positions(&mut out) != 0 {}
+    });
+}
+
+fn count_gdp_csv(bench: &mut Bencher) {
+    bench.iter(|| {
+        bufchr::count(CSV_HAYSTACK, b',')
+    });
+}
+
+fn count_gdp_csv3(bench: &mut Bencher) {
+    bench.iter(|| {
+        bufchr::count3(CSV_HAYSTACK, b',', b'"', b'\n')
+    });
+}
+
+fn count_gdp_csv3_csv(bench: &mut Bencher) {
+    bench.iter(|| {
+        bufchr::count_csv(CSV_HAYSTACK, b',')
+    });
+}
+
+benchmark_group!(benches, read_gdp_csv, read_gdp_csv2, read_gdp_csv3, read_gdp_csv3_csv,
+    fill_gdp_csv3_csv, count_gdp_csv, count_gdp_csv3, count_gdp_csv3_csv);
 benchmark_main!(benches);
\ No newline at end of file
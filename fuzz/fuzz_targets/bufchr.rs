@@ -0,0 +1,31 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bufchr::{Backend, Bufchr};
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let ([n1, _, _], haystack) = match common::split(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let expected = common::positions(haystack, |b| b == n1);
+
+    common::check("dispatch", Some(Bufchr::new(haystack, n1)), &expected);
+    common::check("avx2", Bufchr::with_backend(haystack, n1, Backend::Avx2), &expected);
+    common::check("sse2", Bufchr::with_backend(haystack, n1, Backend::Sse2), &expected);
+    common::check("portable", Bufchr::with_backend(haystack, n1, Backend::Portable), &expected);
+    common::check("scalar", Bufchr::with_backend(haystack, n1, Backend::Scalar), &expected);
+
+    let mut bf = Bufchr::new(haystack, n1);
+    let mut out = [0u32; 7];
+    let mut filled = Vec::new();
+    loop {
+        let written = bf.fill_positions(&mut out);
+        if written == 0 {
+            break;
+        }
+        filled.extend(out[..written].iter().map(|&p| p as usize));
+    }
+    assert_eq!(filled, expected, "fill_positions");
+});
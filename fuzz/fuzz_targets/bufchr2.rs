@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bufchr::{Backend, Bufchr2};
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let ([n1, n2, _], haystack) = match common::split(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let expected = common::positions(haystack, |b| b == n1 || b == n2);
+
+    common::check("dispatch", Some(Bufchr2::new(haystack, n1, n2)), &expected);
+    common::check("avx2", Bufchr2::with_backend(haystack, n1, n2, Backend::Avx2), &expected);
+    common::check("sse2", Bufchr2::with_backend(haystack, n1, n2, Backend::Sse2), &expected);
+    common::check("portable", Bufchr2::with_backend(haystack, n1, n2, Backend::Portable), &expected);
+    common::check("scalar", Bufchr2::with_backend(haystack, n1, n2, Backend::Scalar), &expected);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bufchr::{Backend, Bufchr3};
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let ([n1, n2, n3], haystack) = match common::split(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let expected = common::positions(haystack, |b| b == n1 || b == n2 || b == n3);
+
+    common::check("dispatch", Some(Bufchr3::new(haystack, n1, n2, n3)), &expected);
+    common::check("avx2", Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Avx2), &expected);
+    common::check("sse2", Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Sse2), &expected);
+    common::check("portable", Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Portable), &expected);
+    common::check("scalar", Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Scalar), &expected);
+});
@@ -0,0 +1,20 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bufchr::{Backend, BufchrCSV};
+
+mod common;
+
+fuzz_target!(|data: &[u8]| {
+    let ([n1, _, _], haystack) = match common::split(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let expected = common::positions(haystack, |b| b == n1 || b == b'\n' || b == b'"');
+
+    common::check("dispatch", Some(BufchrCSV::new(haystack, n1)), &expected);
+    common::check("avx2", BufchrCSV::with_backend(haystack, n1, Backend::Avx2), &expected);
+    common::check("sse2", BufchrCSV::with_backend(haystack, n1, Backend::Sse2), &expected);
+    common::check("portable", BufchrCSV::with_backend(haystack, n1, Backend::Portable), &expected);
+    common::check("scalar", BufchrCSV::with_backend(haystack, n1, Backend::Scalar), &expected);
+    assert_eq!(bufchr::count_csv(haystack, n1), expected.len(), "count_csv");
+});
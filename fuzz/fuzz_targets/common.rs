@@ -0,0 +1,27 @@
+/// Splits the fuzz input into needles and haystack.
+/// The first byte moves the start of haystack, so every misalignment of the vector loads is reached.
+pub fn split(data: &[u8]) -> Option<([u8; 3], &[u8])> {
+    if data.len() < 4 {
+        return None;
+    }
+    let offset = (data[0] % 64) as usize;
+    let needles = [data[1], data[2], data[3]];
+    let rest = &data[4..];
+    Some((needles, &rest[offset.min(rest.len())..]))
+}
+
+/// Naive reference: every position whose byte is a needle.
+pub fn positions(haystack: &[u8], is_needle: impl Fn(u8) -> bool) -> Vec<usize> {
+    haystack.iter().enumerate()
+        .filter(|&(_, &b)| is_needle(b))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Compares the whole position sequence of an iterator with the reference.
+/// None means the backend is not supported by this cpu.
+pub fn check<I: Iterator<Item = usize>>(backend: &str, iter: Option<I>, expected: &[usize]) {
+    if let Some(iter) = iter {
+        assert_eq!(iter.collect::<Vec<_>>(), expected, "backend: {}", backend);
+    }
+}
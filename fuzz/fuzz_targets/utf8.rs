@@ -0,0 +1,46 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use bufchr::{Backend, Bufchr, BufchrCSV};
+
+mod common;
+
+// validate_utf8 must not change the positions, and must find the error of core::str
+// and the same text between every two positions.
+fuzz_target!(|data: &[u8]| {
+    let ([n1, _, _], haystack) = match common::split(data) {
+        Some(input) => input,
+        None => return,
+    };
+    let error = std::str::from_utf8(haystack).err().map(|err| err.valid_up_to());
+    // A needle that is not ASCII can be inside a character, and get_str only cuts at character boundaries.
+    let valid = std::str::from_utf8(&haystack[..error.unwrap_or(haystack.len())]).unwrap();
+    let text = |start: usize, end: usize| valid.get(start..end);
+    let expected = common::positions(haystack, |b| b == n1);
+    let expected_csv = common::positions(haystack, |b| b == n1 || b == b'\n' || b == b'"');
+    for &backend in &[Backend::Avx2, Backend::Sse2, Backend::Portable, Backend::Scalar] {
+        if let Some(bf) = Bufchr::with_backend(haystack, n1, backend) {
+            let mut bf = bf.validate_utf8(true);
+            let mut start = 0;
+            let mut positions = Vec::new();
+            while let Some(end) = bf.next() {
+                assert_eq!(bf.get_str(start, end), text(start, end), "backend: {}", backend);
+                positions.push(end);
+                start = end + 1;
+            }
+            assert_eq!(positions, expected, "backend: {}", backend);
+            assert_eq!(bf.utf8_error(), error, "backend: {}", backend);
+        }
+        if let Some(bf) = BufchrCSV::with_backend(haystack, n1, backend) {
+            let mut bf = bf.validate_utf8(true);
+            let mut start = 0;
+            let mut positions = Vec::new();
+            while let Some(end) = bf.next() {
+                assert_eq!(bf.get_str(start, end), text(start, end), "csv backend: {}", backend);
+                positions.push(end);
+                start = end + 1;
+            }
+            assert_eq!(positions, expected_csv, "csv backend: {}", backend);
+            assert_eq!(bf.utf8_error(), error, "csv backend: {}", backend);
+        }
+    }
+});
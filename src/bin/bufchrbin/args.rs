@@ -0,0 +1,261 @@
+use bufchr::Backend;
+
+use crate::Error;
+
+pub const DEFAULT_BUFFER_SIZE: usize = 8 << 20;
+
+/// Message of the `with_backend` constructors that are given `Args::backend`, which `parse` checks to be available.
+pub const AVAILABLE: &str = "the backend is checked by Args::parse";
+
+pub const USAGE: &str = "\
+usage: bufchrbin <command> [options] [file ...]
+       bufchrbin grep [options] <pattern> [file ...]
+
+Reads the files, or stdin when there is none or the file is '-'.
+
+commands:
+  count       number of bytes that are one of the needles
+  positions   byte offset of every needle
+  lines       number, byte offset and length of every line
+  csv         fields of every csv record
+  cut         selected csv columns, e.g. -f 2,5-7 or -f name,price
+  csv2json    csv records as a JSON array of objects keyed by the header record
+  csv2jsonl   csv records as JSON objects keyed by the header record, one per line
+  grep        lines that contain a literal pattern, or one of the needles with -n.
+              Exits with 1 when no line matched
+  wc          lines, csv records, fields per record (min/max/mode) and bytes
+  lint        problems of csv records with their record, line, column and byte offset:
+              unclosed or stray quotes, text after a closing quote, another field count
+              than the first record and bare carriage returns. Exits with 1 when there is one
+  bench       time every search mode over the input
+
+options:
+  -n, --needle <bytes>      needles as an escaped byte string, e.g. ',' '\\n\\t' 0x1f (up to 3)
+  -e, --pattern <bytes>     grep pattern as an escaped byte string, instead of the first argument
+  -c, --count               grep and lint print the number of matching lines or problems
+  -d, --delimiter <byte>    csv delimiter (default ',')
+  -f, --fields <list>       cut columns: 1-based numbers, ranges (5-7, 3-, -2) or header names
+  -b, --backend <name>      avx2, sse2, portable or scalar (default: the best one of the cpu)
+  -i, --iterations <n>      bench runs of each search mode (default 5)
+      --buffer-size <bytes> read buffer of wc, cut, csv2json and grep, which stream their input (default 8388608)
+      --json                print JSON Lines instead of text
+  -h, --help                print this help
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Count,
+    Positions,
+    Lines,
+    Csv,
+    Cut,
+    Csv2Json,
+    Csv2Jsonl,
+    Grep,
+    Wc,
+    Lint,
+    Bench,
+}
+
+impl Command {
+    fn parse(name: &str) -> Option<Command> {
+        match name {
+            "count" => Some(Command::Count),
+            "positions" => Some(Command::Positions),
+            "lines" => Some(Command::Lines),
+            "csv" => Some(Command::Csv),
+            "cut" => Some(Command::Cut),
+            "csv2json" => Some(Command::Csv2Json),
+            "csv2jsonl" => Some(Command::Csv2Jsonl),
+            "grep" => Some(Command::Grep),
+            "wc" => Some(Command::Wc),
+            "lint" => Some(Command::Lint),
+            "bench" => Some(Command::Bench),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub command: Command,
+    pub needles: Vec<u8>,
+    pub pattern: Option<Vec<u8>>,
+    pub count_only: bool,
+    pub delimiter: u8,
+    pub fields: Option<String>,
+    /// The backend of `--backend`, or the default one. It is available on this cpu.
+    pub backend: Backend,
+    pub iterations: usize,
+    pub buffer_size: usize,
+    pub json: bool,
+    pub files: Vec<String>,
+}
+
+impl Args {
+    /// Parses the arguments without the program name. None is returned for `--help`.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Option<Args>, Error> {
+        let command = match args.next() {
+            Some(ref arg) if arg == "-h" || arg == "--help" || arg == "help" => return Ok(None),
+            Some(arg) => Command::parse(&arg).ok_or_else(|| Error::Usage(format!("unknown command '{}'", arg)))?,
+            None => return Err(Error::Usage("missing command".to_string())),
+        };
+        let mut parsed = Args {
+            command,
+            needles: Vec::new(),
+            pattern: None,
+            count_only: false,
+            delimiter: b',',
+            fields: None,
+            backend: bufchr::active_backend(),
+            iterations: 5,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            json: false,
+            files: Vec::new(),
+        };
+
+        let mut options_done = false;
+        while let Some(arg) = args.next() {
+            if options_done || arg == "-" || !arg.starts_with('-') {
+                parsed.files.push(arg);
+                continue;
+            }
+            if arg == "--" {
+                options_done = true;
+                continue;
+            }
+            // --name=value, -nvalue or a value in the next argument.
+            let (name, inline_value) = match arg.find('=') {
+                Some(eq) if arg.starts_with("--") => (arg[..eq].to_string(), Some(arg[eq + 1..].to_string())),
+                _ if !arg.starts_with("--") && arg.len() > 2 && arg.is_char_boundary(2) =>
+                    (arg[..2].to_string(), Some(arg[2..].to_string())),
+                _ => (arg.clone(), None),
+            };
+            let mut value = || -> Result<String, Error> {
+                match inline_value.clone() {
+                    Some(value) => Ok(value),
+                    None => args.next().ok_or_else(|| Error::Usage(format!("{} needs a value", name))),
+                }
+            };
+            match name.as_str() {
+                "-n" | "--needle" => parsed.needles.extend(parse_bytes(&value()?)?),
+                "-e" | "--pattern" => parsed.pattern = Some(parse_bytes(&value()?)?),
+                "-c" | "--count" => parsed.count_only = true,
+                "-d" | "--delimiter" => parsed.delimiter = parse_byte(&value()?)?,
+                "-f" | "--fields" => parsed.fields = Some(value()?),
+                "-b" | "--backend" => {
+                    let value = value()?;
+                    let backend = value.parse::<Backend>()
+                        .map_err(|_| Error::Usage(format!("unknown backend '{}'", value)))?;
+                    if !backend.is_available() {
+                        return Err(Error::Usage(format!("backend {} is not available on this cpu", backend)));
+                    }
+                    parsed.backend = backend;
+                }
+                "-i" | "--iterations" => {
+                    let value = value()?;
+                    parsed.iterations = parse_positive(&value, "iterations")?;
+                }
+                "--buffer-size" => {
+                    let value = value()?;
+                    parsed.buffer_size = parse_positive(&value, "buffer size")?;
+                }
+                "--json" => parsed.json = true,
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(Error::Usage(format!("unknown option '{}'", arg))),
+            }
+        }
+
+        if parsed.needles.len() > 3 {
+            return Err(Error::Usage("at most 3 needles are supported".to_string()));
+        }
+        if parsed.delimiter == b'\n' || parsed.delimiter == b'"' {
+            return Err(Error::Usage("the delimiter must not be a line feed or a quote".to_string()));
+        }
+        // Like grep, the first argument is the pattern unless it was given as an option.
+        if command == Command::Grep && parsed.pattern.is_none() && parsed.needles.is_empty() && !parsed.files.is_empty() {
+            parsed.pattern = Some(parse_bytes(&parsed.files.remove(0))?);
+        }
+        if parsed.files.is_empty() {
+            parsed.files.push("-".to_string());
+        }
+        Ok(Some(parsed))
+    }
+
+    /// The needles, or default when none was given.
+    pub fn needles_or(&self, default: &[u8]) -> Vec<u8> {
+        if self.needles.is_empty() { default.to_vec() } else { self.needles.clone() }
+    }
+}
+
+/// Parses an escaped byte string: `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"` and `\xHH` are escapes,
+/// the whole string may be a hex byte like `0x1f` and surrounding quotes are removed.
+pub fn parse_bytes(s: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::Usage(format!("invalid byte string '{}'", s));
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        return match parse_hex(hex.as_bytes()) {
+            Some(byte) => Ok(vec![byte]),
+            None => Err(invalid()),
+        };
+    }
+    let mut bytes = s.as_bytes();
+    if bytes.len() >= 3 {
+        if let (Some(&first), Some(&last)) = (bytes.first(), bytes.last()) {
+            if first == last && (first == b'\'' || first == b'"') {
+                bytes = &bytes[1..bytes.len() - 1];
+            }
+        }
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        let escaped = match iter.next() {
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'0') => 0,
+            Some(b'\\') => b'\\',
+            Some(b'\'') => b'\'',
+            Some(b'"') => b'"',
+            Some(b'x') => {
+                let hex = iter.as_slice().get(..2).ok_or_else(invalid)?;
+                let byte = parse_hex(hex).ok_or_else(invalid)?;
+                iter.nth(1);
+                byte
+            }
+            _ => return Err(invalid()),
+        };
+        out.push(escaped);
+    }
+    if out.is_empty() {
+        return Err(invalid());
+    }
+    Ok(out)
+}
+
+/// Like `parse_bytes`, but exactly one byte.
+pub fn parse_byte(s: &str) -> Result<u8, Error> {
+    match parse_bytes(s)?.as_slice() {
+        &[byte] => Ok(byte),
+        _ => Err(Error::Usage(format!("'{}' is not a single byte", s))),
+    }
+}
+
+fn parse_positive(value: &str, what: &str) -> Result<usize, Error> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(Error::Usage(format!("invalid {} '{}'", what, value))),
+    }
+}
+
+/// One or two hex digits.
+fn parse_hex(hex: &[u8]) -> Option<u8> {
+    if hex.is_empty() || hex.len() > 2 || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()
+}
@@ -0,0 +1,70 @@
+use std::hint::black_box;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use bufchr::{Backend, Bufchr, Bufchr2, Bufchr3, BufchrCSV};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::json_string;
+use crate::search::count_needles;
+use crate::{read_input, Error};
+
+/// The needles of a csv file, used when none is given.
+const DEFAULT_NEEDLES: &[u8] = b",\n\"";
+
+/// Times every search mode over each input and prints the best of args.iterations runs.
+/// The csv modes use the first needle as the delimiter.
+pub fn run<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    let needles = args.needles_or(DEFAULT_NEEDLES);
+    let backend = args.backend;
+    for file in &args.files {
+        let data = read_input(file)?;
+        let data = &data[..];
+        // Named like the functions: bufchr, bufchr2, bufchr3.
+        let n = if needles.len() == 1 { String::new() } else { needles.len().to_string() };
+        let modes: [(String, &dyn Fn() -> usize); 7] = [
+            ("naive".to_string(), &|| data.iter().filter(|b| needles.contains(b)).count()),
+            (format!("bufchr{}", n), &|| count_positions(data, &needles, backend)),
+            (format!("count{}", n), &|| count_needles(data, &needles, backend)),
+            ("bufchr_csv".to_string(), &|| BufchrCSV::with_backend(data, needles[0], backend).expect(AVAILABLE).count()),
+            ("count_csv".to_string(), &|| bufchr::count_csv_with_backend(data, needles[0], backend).expect(AVAILABLE)),
+            // The positions and the UTF-8 check in one pass, against the check alone.
+            ("bufchr_csv_utf8".to_string(), &|| BufchrCSV::with_backend(data, needles[0], backend).expect(AVAILABLE).validate_utf8(true).count()),
+            ("from_utf8".to_string(), &|| std::str::from_utf8(data).map_or_else(|err| err.valid_up_to(), str::len)),
+        ];
+
+        if !args.json {
+            writeln!(out, "{}: {} bytes, backend {}", file, data.len(), backend)?;
+        }
+        for (name, mode) in modes.iter() {
+            let mut best = Duration::MAX;
+            let mut count = 0;
+            for _ in 0..args.iterations {
+                let before = Instant::now();
+                count = black_box(mode());
+                best = best.min(before.elapsed());
+            }
+            let mb_per_s = data.len() as f64 / 1e6 / best.as_secs_f64().max(1e-9);
+            if args.json {
+                out.write_all(b"{\"file\":")?;
+                json_string(out, file.as_bytes())?;
+                writeln!(out, ",\"bytes\":{},\"backend\":\"{}\",\"mode\":\"{}\",\"count\":{},\"seconds\":{:.6},\"mb_per_s\":{:.1}}}",
+                    data.len(), backend, name, count, best.as_secs_f64(), mb_per_s)?;
+            }
+            else {
+                writeln!(out, "{:<12}\t{:>12}\t{:>10.3?}\t{:>9.1} MB/s", name, count, best, mb_per_s)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Like `search::find(..).count()`, without the boxed iterator in the timed loop.
+fn count_positions(data: &[u8], needles: &[u8], backend: Backend) -> usize {
+    match *needles {
+        [n1] => Bufchr::with_backend(data, n1, backend).expect(AVAILABLE).count(),
+        [n1, n2] => Bufchr2::with_backend(data, n1, n2, backend).expect(AVAILABLE).count(),
+        [n1, n2, n3] => Bufchr3::with_backend(data, n1, n2, n3, backend).expect(AVAILABLE).count(),
+        _ => unreachable!("1 to 3 needles"),
+    }
+}
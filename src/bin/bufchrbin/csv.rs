@@ -0,0 +1,38 @@
+use std::io::Write;
+
+use bufchr::csv::{CsvDialect, Fields};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::{json_string, text_field};
+use crate::{read_input, utf16_to_utf8, Error};
+
+/// Prints every record: a JSON array of strings per line, or the fields separated by tabs.
+pub fn records<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    for file in &args.files {
+        let data = utf16_to_utf8(file, read_input(file)?)?;
+        let mut first = true;
+        let mut fields = Fields::with_backend(&data, CsvDialect::new(args.delimiter), args.backend).expect(AVAILABLE).unescaped();
+        while let Some(field) = fields.next() {
+            let value = &field.data[..];
+            if args.json {
+                out.write_all(if first { b"[" } else { b"," })?;
+                json_string(out, value)?;
+                if field.last {
+                    out.write_all(b"]\n")?;
+                }
+            }
+            else {
+                if !first {
+                    out.write_all(b"\t")?;
+                }
+                text_field(out, value)?;
+                if field.last {
+                    out.write_all(b"\n")?;
+                }
+            }
+            first = field.last;
+            fields.recycle(field.data);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::io::{self, ErrorKind, Write};
+
+use bufchr::csv::UnescapedField;
+
+use crate::args::Args;
+use crate::output::json_string;
+use crate::stream::csv_records;
+use crate::{open_input, Error};
+
+/// The keys of the columns, each written once as a JSON string followed by a colon.
+struct Keys {
+    keys: Vec<Vec<u8>>,
+    // Number of columns of the header record.
+    header_len: usize,
+    taken: HashSet<Vec<u8>>,
+}
+
+impl Keys {
+    /// Returns the keys of the header record, or an error when two of its columns have the same key.
+    fn new(header: &[UnescapedField<'_>], name: &str) -> Result<Keys, Error> {
+        let mut keys = Vec::with_capacity(header.len());
+        let mut taken = HashSet::new();
+        for field in header {
+            let mut key = Vec::new();
+            json_string(&mut key, &field.data)?;
+            key.push(b':');
+            if !taken.insert(key.clone()) {
+                let msg = format!("duplicate column name {} in the header", String::from_utf8_lossy(&key[..key.len() - 1]));
+                return Err(Error::Input(name.to_string(), io::Error::new(ErrorKind::InvalidData, msg)));
+            }
+            keys.push(key);
+        }
+        Ok(Keys { header_len: keys.len(), keys, taken })
+    }
+
+    /// The key of column. A column without a header is keyed by its 1-based number after an underscore,
+    /// with one more underscore as long as a header column has that name.
+    fn get(&mut self, column: usize) -> &[u8] {
+        while self.keys.len() <= column {
+            let mut key = format!("\"_{}\":", self.keys.len() + 1).into_bytes();
+            while self.taken.contains(&key) {
+                key.insert(1, b'_');
+            }
+            self.keys.push(key);
+        }
+        &self.keys[column]
+    }
+}
+
+/// Writes record as a JSON object with keys. A header column the record does not have is null.
+fn write_object<W: Write>(out: &mut W, keys: &mut Keys, record: &[UnescapedField<'_>]) -> Result<(), Error> {
+    out.write_all(b"{")?;
+    for column in 0..keys.header_len.max(record.len()) {
+        if column > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(keys.get(column))?;
+        match record.get(column) {
+            Some(field) => json_string(out, &field.data)?,
+            None => out.write_all(b"null")?,
+        }
+    }
+    out.write_all(b"}")?;
+    Ok(())
+}
+
+/// Converts the records to JSON objects keyed by the header record of their file.
+/// With lines, every object is printed on its own line (JSON Lines), otherwise all of them form one array.
+pub fn run<W: Write>(args: &Args, out: &mut W, lines: bool) -> Result<(), Error> {
+    let mut first = true;
+    if !lines {
+        out.write_all(b"[")?;
+    }
+    for file in &args.files {
+        let mut header: Option<Keys> = None;
+        csv_records(open_input(file)?, file, args.delimiter, args.backend, args.buffer_size, |record| {
+            let keys = match header {
+                Some(ref mut keys) => keys,
+                None => {
+                    header = Some(Keys::new(record, file)?);
+                    return Ok(());
+                }
+            };
+            if !lines {
+                out.write_all(if first { b"\n" } else { b",\n" })?;
+            }
+            first = false;
+            write_object(out, keys, record)?;
+            if lines {
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        })?;
+    }
+    if !lines {
+        out.write_all(if first { b"]\n" } else { b"\n]\n" })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use std::io::Write;
+
+use bufchr::csv::UnescapedField;
+
+use crate::args::Args;
+use crate::output::csv_field;
+use crate::stream::csv_records;
+use crate::{open_input, Error};
+
+/// One item of a field list like `2,5-7,name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    /// A 1-based column.
+    Column(usize),
+    /// 1-based columns from, to inclusive. No end means up to the last column of each record.
+    Range(usize, Option<usize>),
+    /// The column with this header name.
+    Name(String),
+}
+
+fn parse_column(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parses a field list. Numbers and ranges (`5-7`, `3-`, `-2`) are columns, anything else is a header name.
+fn parse_list(list: &str) -> Result<Vec<Selector>, Error> {
+    let mut selectors = Vec::new();
+    for item in list.split(',') {
+        let selector = if let Some(column) = parse_column(item) {
+            Selector::Column(column)
+        }
+        else {
+            match item.find('-').map(|dash| (&item[..dash], &item[dash + 1..])) {
+                Some((from, to)) if (from.is_empty() || parse_column(from).is_some())
+                    && (to.is_empty() || parse_column(to).is_some()) && item != "-" => {
+                    let from = parse_column(from).unwrap_or(1);
+                    let to = parse_column(to);
+                    if to.is_some_and(|to| to < from) {
+                        return Err(Error::Usage(format!("invalid range '{}'", item)));
+                    }
+                    Selector::Range(from, to)
+                }
+                _ if item.is_empty() => return Err(Error::Usage(format!("empty item in field list '{}'", list))),
+                _ => Selector::Name(item.to_string()),
+            }
+        };
+        if selector == Selector::Column(0) || matches!(selector, Selector::Range(0, _)) {
+            return Err(Error::Usage("columns are numbered from 1".to_string()));
+        }
+        selectors.push(selector);
+    }
+    Ok(selectors)
+}
+
+/// Replaces the names with the columns of header.
+fn resolve(selectors: &[Selector], header: &[UnescapedField<'_>]) -> Result<Vec<Selector>, Error> {
+    selectors.iter().map(|selector| match selector {
+        Selector::Name(name) => header.iter()
+            .position(|field| &*field.data == name.as_bytes())
+            .map(|i| Selector::Column(i + 1))
+            .ok_or_else(|| Error::Usage(format!("no column named '{}'", name))),
+        other => Ok(other.clone()),
+    }).collect()
+}
+
+/// Appends the 0-based columns of record selected by selectors to columns.
+/// An open range ends at the last column of the record.
+fn expand(selectors: &[Selector], record_len: usize, columns: &mut Vec<usize>) {
+    for selector in selectors {
+        match *selector {
+            Selector::Column(column) => columns.push(column - 1),
+            Selector::Range(from, to) => columns.extend(from - 1..to.unwrap_or(record_len).max(from - 1)),
+            Selector::Name(_) => unreachable!("names are resolved with the header"),
+        }
+    }
+}
+
+/// Writes the fields of record at columns. A column the record does not have is written empty.
+fn write_record<W: Write>(out: &mut W, record: &[UnescapedField<'_>], columns: &[usize], delimiter: u8) -> Result<(), Error> {
+    let empty = |column: &usize| record.get(*column).is_none_or(|field| field.data.is_empty());
+    if columns.len() <= 1 && columns.iter().all(empty) {
+        // Quoted, so that the record is not read back as a blank line.
+        out.write_all(b"\"\"\n")?;
+        return Ok(());
+    }
+    for (i, &column) in columns.iter().enumerate() {
+        if i > 0 {
+            out.write_all(&[delimiter])?;
+        }
+        if let Some(field) = record.get(column) {
+            csv_field(out, &field.data, delimiter)?;
+        }
+    }
+    out.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Prints the fields of args.fields from every record, quoted again where needed.
+/// Fields are written in the order of the list, and the header is a record like any other.
+pub fn run<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    let list = args.fields.as_deref().ok_or_else(|| Error::Usage("missing field list (-f)".to_string()))?;
+    let selectors = parse_list(list)?;
+    let has_names = selectors.iter().any(|selector| matches!(selector, Selector::Name(_)));
+    for file in &args.files {
+        let mut resolved: Option<Vec<Selector>> = if has_names { None } else { Some(selectors.clone()) };
+        let mut columns = Vec::new();
+        csv_records(open_input(file)?, file, args.delimiter, args.backend, args.buffer_size, |record| {
+            let selectors = match resolved {
+                Some(ref selectors) => selectors,
+                None => resolved.get_or_insert(resolve(&selectors, record)?),
+            };
+            columns.clear();
+            expand(selectors, record.len(), &mut columns);
+            write_record(out, record, &columns, args.delimiter)
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use std::io::{ErrorKind, Read, Write};
+
+use bufchr::{Backend, Bufchr};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::json_string;
+use crate::search::find;
+use crate::{open_input, Error};
+
+/// Bytes of a chunk in which the bytes of a literal are counted.
+const SAMPLE_SIZE: usize = 64 << 10;
+
+/// What a line must contain to match.
+enum Pattern {
+    /// Any of 1 to 3 bytes.
+    Bytes(Vec<u8>),
+    /// A literal string without a line feed.
+    Literal(Vec<u8>),
+}
+
+impl Pattern {
+    /// Returns a searcher for chunk. A literal is searched by its byte that is rarest in the start of chunk,
+    /// counted with the count kernels, and every position of that byte is then compared with the literal.
+    fn searcher<'a>(&'a self, chunk: &[u8], backend: Backend) -> Searcher<'a> {
+        match self {
+            Pattern::Bytes(needles) => Searcher { needles, literal: None, rare: 0, backend },
+            Pattern::Literal(literal) => {
+                let sample = &chunk[..chunk.len().min(SAMPLE_SIZE)];
+                let rare = (0..literal.len())
+                    .take(8)
+                    .min_by_key(|&i| bufchr::count_with_backend(sample, literal[i], backend).expect(AVAILABLE))
+                    .unwrap_or(0);
+                Searcher { needles: &literal[rare..rare + 1], literal: Some(literal), rare, backend }
+            }
+        }
+    }
+}
+
+struct Searcher<'a> {
+    needles: &'a [u8],
+    literal: Option<&'a [u8]>,
+    // Index of needles[0] in the literal.
+    rare: usize,
+    backend: Backend,
+}
+
+impl<'a> Searcher<'a> {
+    /// Position of the first match in data at or after from.
+    fn find(&self, data: &[u8], from: usize) -> Option<usize> {
+        let literal = match self.literal {
+            Some(literal) => literal,
+            None => return find(&data[from..], self.needles, self.backend).next().map(|pos| from + pos),
+        };
+        let from_rare = from + self.rare;
+        if from_rare >= data.len() {
+            return None;
+        }
+        Bufchr::with_backend(&data[from_rare..], self.needles[0], self.backend).expect(AVAILABLE)
+            .map(|pos| from_rare + pos - self.rare)
+            .find(|&start| data[start..].starts_with(literal))
+    }
+}
+
+struct Grep<'a, W: Write> {
+    out: &'a mut W,
+    args: &'a Args,
+    file: &'a str,
+    matches: u64,
+}
+
+impl<'a, W: Write> Grep<'a, W> {
+    /// Prints the matching lines of chunk, which holds whole lines. first_line is the number of its first line
+    /// and offset the position of its first byte in the input. Returns the number of line feeds of chunk.
+    fn chunk(&mut self, pattern: &Pattern, chunk: &[u8], first_line: u64, offset: u64) -> Result<u64, Error> {
+        let backend = self.args.backend;
+        let searcher = pattern.searcher(chunk, backend);
+        let mut line = first_line;
+        // Start of the line after the last match, and where line feeds were counted up to.
+        let mut from = 0;
+        let mut counted = 0;
+        while from < chunk.len() {
+            let found = match searcher.find(chunk, from) {
+                Some(found) => found,
+                None => break,
+            };
+            let line_start = chunk[from..found].iter().rposition(|&b| b == b'\n').map_or(from, |i| from + i + 1);
+            let line_end = Bufchr::with_backend(&chunk[found..], b'\n', backend).expect(AVAILABLE).next().map_or(chunk.len(), |pos| found + pos);
+            line += bufchr::count_with_backend(&chunk[counted..line_start], b'\n', backend).expect(AVAILABLE) as u64;
+            counted = line_start;
+            self.matches += 1;
+            if !self.args.count_only {
+                self.print(line, offset + line_start as u64, offset + found as u64, &chunk[line_start..line_end])?;
+            }
+            from = line_end + 1;
+        }
+        Ok(line - first_line + bufchr::count_with_backend(&chunk[counted..], b'\n', backend).expect(AVAILABLE) as u64)
+    }
+
+    fn print(&mut self, line: u64, offset: u64, found: u64, text: &[u8]) -> Result<(), Error> {
+        if self.args.json {
+            self.out.write_all(b"{\"file\":")?;
+            json_string(self.out, self.file.as_bytes())?;
+            write!(self.out, ",\"line\":{},\"offset\":{},\"match\":{},\"text\":", line, offset, found)?;
+            json_string(self.out, text)?;
+            self.out.write_all(b"}\n")?;
+            return Ok(());
+        }
+        if self.args.files.len() > 1 {
+            write!(self.out, "{}:", self.file)?;
+        }
+        write!(self.out, "{}:{}:", line, offset)?;
+        self.out.write_all(text)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+/// Searches the lines of input. The input is read in chunks of buffer_size, cut after their last line feed.
+fn search<R: Read, W: Write>(grep: &mut Grep<'_, W>, pattern: &Pattern, mut input: R, buffer_size: usize) -> Result<(), Error> {
+    let mut buffer = vec![0; buffer_size];
+    let mut filled = 0;
+    let mut line = 1;
+    let mut offset = 0;
+    loop {
+        if filled == buffer.len() {
+            buffer.resize(buffer.len() * 2, 0);
+        }
+        let n = match input.read(&mut buffer[filled..]) {
+            Ok(n) => n,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Input(grep.file.to_string(), err)),
+        };
+        filled += n;
+        let eof = n == 0;
+        let end = if eof {
+            filled
+        }
+        else {
+            match buffer[..filled].iter().rposition(|&b| b == b'\n') {
+                Some(last) => last + 1,
+                None => continue,
+            }
+        };
+        line += grep.chunk(pattern, &buffer[..end], line, offset)?;
+        offset += end as u64;
+        if eof {
+            return Ok(());
+        }
+        buffer.copy_within(end..filled, 0);
+        filled -= end;
+    }
+}
+
+/// Prints the lines that contain one of args.needles or the literal args.pattern, with their line number
+/// and the byte offset of the line, or only their number with args.count_only.
+/// Returns false when no line matched.
+pub fn run<W: Write>(args: &Args, out: &mut W) -> Result<bool, Error> {
+    let pattern = match (&args.pattern, args.needles.is_empty()) {
+        (Some(_), false) => return Err(Error::Usage("give either a pattern or needles (-n), not both".to_string())),
+        (Some(pattern), true) => Pattern::Literal(pattern.clone()),
+        (None, false) => Pattern::Bytes(args.needles.clone()),
+        (None, true) => return Err(Error::Usage("missing pattern".to_string())),
+    };
+    if let Pattern::Literal(ref literal) = pattern {
+        if literal.contains(&b'\n') {
+            return Err(Error::Usage("the pattern must not contain a line feed".to_string()));
+        }
+    }
+    let mut matched = false;
+    for file in &args.files {
+        let mut grep = Grep { out: &mut *out, args, file, matches: 0 };
+        search(&mut grep, &pattern, open_input(file)?, args.buffer_size)?;
+        let matches = grep.matches;
+        matched |= matches > 0;
+        if !args.count_only {
+            continue;
+        }
+        if args.json {
+            out.write_all(b"{\"file\":")?;
+            json_string(out, file.as_bytes())?;
+            writeln!(out, ",\"count\":{}}}", matches)?;
+        }
+        else if args.files.len() > 1 {
+            writeln!(out, "{}:{}", file, matches)?;
+        }
+        else {
+            writeln!(out, "{}", matches)?;
+        }
+    }
+    Ok(matched)
+}
@@ -0,0 +1,49 @@
+use std::io::Write;
+
+use bufchr::csv::{validate_with_backend, CsvDialect};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::json_string;
+use crate::{read_input, Error};
+
+/// Prints the problems of every csv file with their location, or only their number with args.count_only.
+/// Returns false when a file has a problem.
+pub fn run<W: Write>(args: &Args, out: &mut W) -> Result<bool, Error> {
+    let dialect = CsvDialect::new(args.delimiter);
+    let mut valid = true;
+    for file in &args.files {
+        let data = read_input(file)?;
+        let errors = validate_with_backend(&data, dialect, args.backend).expect(AVAILABLE);
+        valid &= errors.is_empty();
+        if args.count_only {
+            if args.json {
+                out.write_all(b"{\"file\":")?;
+                json_string(out, file.as_bytes())?;
+                writeln!(out, ",\"count\":{}}}", errors.len())?;
+            }
+            else if args.files.len() > 1 {
+                writeln!(out, "{}:{}", file, errors.len())?;
+            }
+            else {
+                writeln!(out, "{}", errors.len())?;
+            }
+            continue;
+        }
+        for error in &errors {
+            if args.json {
+                out.write_all(b"{\"file\":")?;
+                json_string(out, file.as_bytes())?;
+                write!(out, ",\"record\":{},\"line\":{},\"column\":{},\"offset\":{},\"error\":",
+                    error.record, error.line, error.column, error.offset)?;
+                json_string(out, error.kind.to_string().as_bytes())?;
+                out.write_all(b"}\n")?;
+                continue;
+            }
+            if args.files.len() > 1 {
+                write!(out, "{}:", file)?;
+            }
+            writeln!(out, "{}", error)?;
+        }
+    }
+    Ok(valid)
+}
@@ -0,0 +1,139 @@
+/*!
+Command line tool for searching bytes and splitting CSV with bufchr.
+
+Run `bufchrbin --help` for the commands and options.
+*/
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process;
+
+use bufchr::csv::{to_utf8, Encoding, EncodingError};
+
+mod args;
+mod bench;
+mod csv;
+mod csv2json;
+mod cut;
+mod grep;
+mod lint;
+mod output;
+mod search;
+mod stream;
+mod wc;
+
+use crate::args::{Args, Command, USAGE};
+
+pub enum Error {
+    /// Invalid arguments. Exit code 2.
+    Usage(String),
+    /// A file could not be read. Exit code 1.
+    Input(String, io::Error),
+    /// The output could not be written. Exit code 1, or 0 for a closed pipe.
+    Output(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Output(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Usage(msg) => write!(f, "{}\nTry 'bufchrbin --help' for more information.", msg),
+            Error::Input(path, err) => write!(f, "{}: {}", path, err),
+            Error::Output(err) => write!(f, "write error: {}", err),
+        }
+    }
+}
+
+/// Reads a whole file, or stdin for `-`.
+pub fn read_input(path: &str) -> Result<Vec<u8>, Error> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data).map_err(|err| Error::Input("stdin".to_string(), err))?;
+        return Ok(data);
+    }
+    fs::read(path).map_err(|err| Error::Input(path.to_string(), err))
+}
+
+/// Returns the data of a csv file as UTF-8: UTF-16 with a byte order mark is transcoded, anything else is kept.
+pub fn utf16_to_utf8(path: &str, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if Encoding::detect(&data).is_utf8() {
+        return Ok(data);
+    }
+    to_utf8(&data)
+        .map(|utf8| utf8.into_owned())
+        .map_err(|err| encoding_error(path, err))
+}
+
+/// The error of a file that could not be transcoded.
+pub fn encoding_error(path: &str, err: EncodingError) -> Error {
+    Error::Input(path.to_string(), io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Opens a file, or stdin for `-`, to be read in chunks.
+pub fn open_input(path: &str) -> Result<Box<dyn Read>, Error> {
+    if path == "-" {
+        return Ok(Box::new(io::stdin()));
+    }
+    let file = fs::File::open(path).map_err(|err| Error::Input(path.to_string(), err))?;
+    Ok(Box::new(file))
+}
+
+/// Runs the command and returns the exit code.
+fn run() -> Result<i32, Error> {
+    let args = match Args::parse(std::env::args().skip(1))? {
+        Some(args) => args,
+        None => {
+            print!("{}", USAGE);
+            return Ok(0);
+        }
+    };
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    let mut code = 0;
+    match args.command {
+        Command::Count => search::count(&args, &mut out)?,
+        Command::Positions => search::positions(&args, &mut out)?,
+        Command::Lines => search::lines(&args, &mut out)?,
+        Command::Csv => csv::records(&args, &mut out)?,
+        Command::Cut => cut::run(&args, &mut out)?,
+        Command::Csv2Json => csv2json::run(&args, &mut out, false)?,
+        Command::Csv2Jsonl => csv2json::run(&args, &mut out, true)?,
+        Command::Grep => {
+            if !grep::run(&args, &mut out)? {
+                code = 1;
+            }
+        }
+        Command::Wc => wc::run(&args, &mut out)?,
+        Command::Lint => {
+            if !lint::run(&args, &mut out)? {
+                code = 1;
+            }
+        }
+        Command::Bench => bench::run(&args, &mut out)?,
+    }
+    out.flush()?;
+    Ok(code)
+}
+
+fn main() {
+    let code = match run() {
+        Ok(code) => code,
+        Err(Error::Output(ref err)) if err.kind() == io::ErrorKind::BrokenPipe => 0,
+        Err(err @ Error::Usage(_)) => {
+            eprintln!("bufchrbin: {}", err);
+            2
+        }
+        Err(err) => {
+            eprintln!("bufchrbin: {}", err);
+            1
+        }
+    };
+    process::exit(code);
+}
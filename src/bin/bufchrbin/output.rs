@@ -0,0 +1,57 @@
+use std::io::{self, Write};
+
+/// Writes bytes as a JSON string. Invalid UTF-8 is replaced with U+FFFD.
+/// The bytes between two escapes are found with `find_json_escape` and written at once.
+pub fn json_string<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    out.write_all(b"\"")?;
+    let text = String::from_utf8_lossy(bytes);
+    let mut rest = text.as_bytes();
+    while let Some(i) = bufchr::find_json_escape(rest) {
+        out.write_all(&rest[..i])?;
+        match rest[i] {
+            b'"' => out.write_all(b"\\\"")?,
+            b'\\' => out.write_all(b"\\\\")?,
+            b'\n' => out.write_all(b"\\n")?,
+            b'\r' => out.write_all(b"\\r")?,
+            b'\t' => out.write_all(b"\\t")?,
+            b => write!(out, "\\u{:04x}", b)?,
+        }
+        rest = &rest[i + 1..];
+    }
+    out.write_all(rest)?;
+    out.write_all(b"\"")
+}
+
+/// Writes a field as text: tab, line feed, carriage return and backslash are escaped
+/// so that a record stays on one line with its fields separated by tabs.
+pub fn text_field<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    for (i, &b) in bytes.iter().enumerate() {
+        let escape: &[u8] = match b {
+            b'\t' => b"\\t",
+            b'\n' => b"\\n",
+            b'\r' => b"\\r",
+            b'\\' => b"\\\\",
+            _ => continue,
+        };
+        out.write_all(&bytes[start..i])?;
+        out.write_all(escape)?;
+        start = i + 1;
+    }
+    out.write_all(&bytes[start..])
+}
+
+/// Writes a csv field, in quotes with its quotes doubled when it has a delimiter, a quote or a line break.
+pub fn csv_field<W: Write>(out: &mut W, bytes: &[u8], delimiter: u8) -> io::Result<()> {
+    if !bytes.iter().any(|&b| b == delimiter || b == b'"' || b == b'\n' || b == b'\r') {
+        return out.write_all(bytes);
+    }
+    out.write_all(b"\"")?;
+    for (i, part) in bytes.split(|&b| b == b'"').enumerate() {
+        if i > 0 {
+            out.write_all(b"\"\"")?;
+        }
+        out.write_all(part)?;
+    }
+    out.write_all(b"\"")
+}
@@ -0,0 +1,114 @@
+use std::io::Write;
+
+use bufchr::{Backend, Bufchr, Bufchr2, Bufchr3};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::json_string;
+use crate::{read_input, Error};
+
+/// Positions of the bytes of data that are one of needles, found with backend. needles has 1 to 3 bytes.
+pub fn find<'a>(data: &'a [u8], needles: &[u8], backend: Backend) -> Box<dyn Iterator<Item = usize> + 'a> {
+    match *needles {
+        [n1] => Box::new(Bufchr::with_backend(data, n1, backend).expect(AVAILABLE)),
+        [n1, n2] => Box::new(Bufchr2::with_backend(data, n1, n2, backend).expect(AVAILABLE)),
+        [n1, n2, n3] => Box::new(Bufchr3::with_backend(data, n1, n2, n3, backend).expect(AVAILABLE)),
+        _ => unreachable!("1 to 3 needles"),
+    }
+}
+
+/// Number of the bytes of data that are one of needles, counted with backend. needles has 1 to 3 bytes.
+pub fn count_needles(data: &[u8], needles: &[u8], backend: Backend) -> usize {
+    match *needles {
+        [n1] => bufchr::count_with_backend(data, n1, backend),
+        [n1, n2] => bufchr::count2_with_backend(data, n1, n2, backend),
+        [n1, n2, n3] => bufchr::count3_with_backend(data, n1, n2, n3, backend),
+        _ => unreachable!("1 to 3 needles"),
+    }.expect(AVAILABLE)
+}
+
+fn required_needles(args: &Args) -> Result<&[u8], Error> {
+    if args.needles.is_empty() {
+        return Err(Error::Usage("missing needle (-n)".to_string()));
+    }
+    Ok(&args.needles)
+}
+
+fn json_file<W: Write>(out: &mut W, file: &str) -> Result<(), Error> {
+    out.write_all(b"{\"file\":")?;
+    json_string(out, file.as_bytes())?;
+    Ok(())
+}
+
+pub fn count<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    let needles = required_needles(args)?;
+    for file in &args.files {
+        let data = read_input(file)?;
+        let count = count_needles(&data, needles, args.backend);
+        if args.json {
+            json_file(out, file)?;
+            writeln!(out, ",\"count\":{}}}", count)?;
+        }
+        else if args.files.len() > 1 {
+            writeln!(out, "{}\t{}", count, file)?;
+        }
+        else {
+            writeln!(out, "{}", count)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn positions<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    let needles = required_needles(args)?;
+    for file in &args.files {
+        let data = read_input(file)?;
+        if args.json {
+            json_file(out, file)?;
+            out.write_all(b",\"positions\":[")?;
+            for (i, position) in find(&data, needles, args.backend).enumerate() {
+                if i > 0 {
+                    out.write_all(b",")?;
+                }
+                write!(out, "{}", position)?;
+            }
+            out.write_all(b"]}\n")?;
+        }
+        else {
+            for position in find(&data, needles, args.backend) {
+                if args.files.len() > 1 {
+                    write!(out, "{}:", file)?;
+                }
+                writeln!(out, "{}", position)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn lines<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    for file in &args.files {
+        let data = read_input(file)?;
+        let mut start = 0;
+        let mut line = 0;
+        let ends = Bufchr::with_backend(&data, b'\n', args.backend).expect(AVAILABLE).chain(Some(data.len()).filter(|&len| len > 0 && data[len - 1] != b'\n'));
+        for end in ends {
+            line += 1;
+            let mut length = end - start;
+            if length > 0 && data[end - 1] == b'\r' {
+                length -= 1;
+            }
+            if args.json {
+                json_file(out, file)?;
+                writeln!(out, ",\"line\":{},\"offset\":{},\"length\":{}}}", line, start, length)?;
+            }
+            else {
+                if args.files.len() > 1 {
+                    write!(out, "{}:", file)?;
+                }
+                writeln!(out, "{}\t{}\t{}", line, start, length)?;
+            }
+            start = end + 1;
+        }
+    }
+    Ok(())
+}
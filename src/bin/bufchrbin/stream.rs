@@ -0,0 +1,158 @@
+use std::io::{ErrorKind, Read};
+
+use bufchr::csv::{CsvDialect, Encoding, EncodingError, Fields, UnescapedField};
+use bufchr::Backend;
+
+use crate::args::AVAILABLE;
+use crate::{encoding_error, Error};
+
+/// Reads from input into buffer like `Read::read`, retrying when interrupted. 0 is returned at the end of input.
+pub fn read<R: Read>(input: &mut R, buffer: &mut [u8], name: &str) -> Result<usize, Error> {
+    loop {
+        match input.read(buffer) {
+            Ok(n) => return Ok(n),
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Input(name.to_string(), err)),
+        }
+    }
+}
+
+/// Transcodes UTF-16 read in chunks to UTF-8. A byte or a high surrogate at the end of a chunk is kept
+/// until the next one completes it, so any chunk size gives the UTF-8 of `csv::to_utf8`,
+/// and an error at the offset it gives for the input read so far.
+pub struct Utf16Decoder {
+    encoding: Encoding,
+    // Bytes at the end of the last chunk that are not transcoded yet: up to 3.
+    pending: Vec<u8>,
+    // Offset of pending[0] in the input.
+    offset: usize,
+}
+
+impl Utf16Decoder {
+    /// Decoder of UTF-16 in encoding whose first chunk starts at offset, after the byte order mark.
+    pub fn new(encoding: Encoding, offset: usize) -> Utf16Decoder {
+        debug_assert!(matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be));
+        Utf16Decoder { encoding, pending: Vec::with_capacity(4), offset }
+    }
+
+    /// Appends the UTF-8 of chunk to out. With eof, chunk ends the input, so nothing may be left incomplete.
+    pub fn decode(&mut self, chunk: &[u8], eof: bool, out: &mut Vec<u8>) -> Result<(), EncodingError> {
+        let unit: fn([u8; 2]) -> u16 = match self.encoding {
+            Encoding::Utf16Be => u16::from_be_bytes,
+            _ => u16::from_le_bytes,
+        };
+        let pending = &self.pending;
+        let byte = |i: usize| if i < pending.len() { pending[i] } else { chunk[i - pending.len()] };
+        let len = pending.len() + chunk.len();
+        let mut end = len / 2 * 2;
+        // The low surrogate of a pair cut by the chunk is at the start of the next one.
+        if !eof && end >= 2 && (0xD800..0xDC00).contains(&unit([byte(end - 2), byte(end - 1)])) {
+            end -= 2;
+        }
+        if eof && end < len {
+            return Err(EncodingError { encoding: self.encoding, offset: self.offset + len - 1 });
+        }
+        out.reserve(end / 2 * 3);
+        // Offset of the next code unit from pending[0].
+        let mut offset = 0;
+        let units = (0..end).step_by(2).map(|i| unit([byte(i), byte(i + 1)]));
+        for c in core::char::decode_utf16(units) {
+            let c = c.map_err(|_| EncodingError { encoding: self.encoding, offset: self.offset + offset })?;
+            offset += c.len_utf16() * 2;
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        let mut rest = [0; 3];
+        for (i, b) in rest[..len - end].iter_mut().enumerate() {
+            *b = byte(end + i);
+        }
+        self.pending.clear();
+        self.pending.extend_from_slice(&rest[..len - end]);
+        self.offset += end;
+        Ok(())
+    }
+}
+
+/// Calls f with the unescaped fields of every csv record of input, which is read in chunks of buffer_size
+/// and searched with backend.
+/// Only whole records are split: the bytes of an unfinished record are kept for the next chunk,
+/// and the buffer grows when one record is larger than it.
+/// A UTF-8 byte order mark is dropped, and UTF-16 is transcoded to UTF-8 one chunk at a time.
+pub fn csv_records<R, F>(mut input: R, name: &str, delimiter: u8, backend: Backend, buffer_size: usize, mut f: F) -> Result<(), Error>
+    where R: Read, F: FnMut(&[UnescapedField<'_>]) -> Result<(), Error> {
+    let mut buffer = vec![0; buffer_size];
+    let mut filled = 0;
+    let mut detected = false;
+    // For UTF-16, the decoder and the chunk it is read into. Its UTF-8 is appended to the records kept in buffer.
+    let mut utf16: Option<(Utf16Decoder, Vec<u8>)> = None;
+    loop {
+        let n = match utf16 {
+            Some((ref mut decoder, ref mut chunk)) => {
+                let n = read(&mut input, chunk, name)?;
+                buffer.truncate(filled);
+                decoder.decode(&chunk[..n], n == 0, &mut buffer).map_err(|err| encoding_error(name, err))?;
+                filled = buffer.len();
+                n
+            }
+            None => {
+                if filled == buffer.len() {
+                    buffer.resize(buffer.len() * 2, 0);
+                }
+                let n = read(&mut input, &mut buffer[filled..], name)?;
+                filled += n;
+                n
+            }
+        };
+        let eof = n == 0;
+
+        if !detected {
+            // A byte order mark is only looked for once its bytes are there.
+            if filled < 3 && !eof {
+                continue;
+            }
+            detected = true;
+            let encoding = Encoding::detect(&buffer[..filled]);
+            if encoding == Encoding::Utf8Bom {
+                buffer.copy_within(3..filled, 0);
+                filled -= 3;
+            }
+            else if !encoding.is_utf8() {
+                let start = encoding.bom_len();
+                let mut decoder = Utf16Decoder::new(encoding, start);
+                let mut utf8 = Vec::with_capacity(buffer_size);
+                decoder.decode(&buffer[start..filled], eof, &mut utf8).map_err(|err| encoding_error(name, err))?;
+                filled = utf8.len();
+                utf16 = Some((decoder, buffer));
+                buffer = utf8;
+            }
+        }
+
+        let data = &buffer[..filled];
+        // The byte order mark is gone, and a chunk after the first one does not start the file.
+        let mut fields = Fields::with_backend(data, CsvDialect::new(delimiter), backend).expect(AVAILABLE)
+            .detect_encoding(false).unescaped();
+        // Start of the first record that was not passed to f.
+        let mut consumed = 0;
+        let mut record = Vec::new();
+        while let Some(field) = fields.next() {
+            let last = field.last;
+            record.push(field);
+            if !last {
+                continue;
+            }
+            // A record ended by the end of the chunk may go on in the next one.
+            if !eof && fields.position() > data.len() {
+                break;
+            }
+            f(&record)?;
+            for field in record.drain(..) {
+                fields.recycle(field.data);
+            }
+            consumed = fields.position().min(data.len());
+        }
+        if eof {
+            return Ok(());
+        }
+        buffer.copy_within(consumed..filled, 0);
+        filled -= consumed;
+    }
+}
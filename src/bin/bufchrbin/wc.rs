@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use bufchr::csv::Encoding;
+use bufchr::{Backend, BufchrCSV};
+
+use crate::args::{Args, AVAILABLE};
+use crate::output::json_string;
+use crate::stream::{read, Utf16Decoder};
+use crate::{encoding_error, open_input, Error};
+
+/// Counts of one input, or the total of several.
+#[derive(Default)]
+struct Stats {
+    bytes: u64,
+    lines: u64,
+    records: u64,
+    // Number of records for each number of fields.
+    fields: BTreeMap<usize, u64>,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.bytes += other.bytes;
+        self.lines += other.lines;
+        self.records += other.records;
+        for (&fields, &records) in &other.fields {
+            *self.fields.entry(fields).or_insert(0) += records;
+        }
+    }
+
+    /// (min, max, mode) of the fields per record. The smallest one wins a tie for the mode.
+    fn fields_summary(&self) -> Option<(usize, usize, usize)> {
+        let min = *self.fields.keys().next()?;
+        let max = *self.fields.keys().next_back()?;
+        let mut mode = (min, 0);
+        for (&fields, &records) in &self.fields {
+            if records > mode.1 {
+                mode = (fields, records);
+            }
+        }
+        Some((min, max, mode.0))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Quote {
+    None,
+    Open,
+    // Position of the closing quote. A quote right after it is an escaped quote.
+    Closed(u64),
+}
+
+/// Counts the records of a csv stream fed in chunks of any size, with the same rules as `bufchr::csv::Fields`:
+/// a quote opens a field only at its start, line feeds inside quotes belong to the field and blank lines are skipped.
+struct Counter {
+    delimiter: u8,
+    backend: Backend,
+    stats: Stats,
+    // Offset of the next chunk.
+    offset: u64,
+    field_start: u64,
+    quote: Quote,
+    // Delimiters seen in the current record.
+    delimiters: usize,
+    last_byte: u8,
+}
+
+impl Counter {
+    fn new(delimiter: u8, backend: Backend) -> Counter {
+        Counter {
+            delimiter,
+            backend,
+            stats: Stats::default(),
+            offset: 0,
+            field_start: 0,
+            quote: Quote::None,
+            delimiters: 0,
+            last_byte: b'\n',
+        }
+    }
+
+    /// Counts len bytes at the start of the stream, e.g. a byte order mark, as bytes only.
+    /// The first field starts after them.
+    fn skip(&mut self, len: usize) {
+        self.stats.bytes += len as u64;
+        self.offset += len as u64;
+        self.field_start = self.offset;
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        for pos in BufchrCSV::with_backend(chunk, self.delimiter, self.backend).expect(AVAILABLE) {
+            let b = chunk[pos];
+            let position = self.offset + pos as u64;
+            if b == b'\n' {
+                self.stats.lines += 1;
+            }
+            match self.quote {
+                Quote::Open => {
+                    if b == b'"' {
+                        self.quote = Quote::Closed(position);
+                    }
+                    continue;
+                }
+                Quote::Closed(close) => {
+                    self.quote = Quote::None;
+                    if b == b'"' && position == close + 1 {
+                        self.quote = Quote::Open;
+                        continue;
+                    }
+                }
+                Quote::None => {}
+            }
+            if b == b'"' {
+                if position == self.field_start {
+                    self.quote = Quote::Open;
+                }
+            }
+            else if b == self.delimiter {
+                self.delimiters += 1;
+                self.field_start = position + 1;
+            }
+            else {
+                if !self.is_blank_line(position, chunk) {
+                    self.end_record();
+                }
+                self.field_start = position + 1;
+            }
+        }
+        if let Some(&last) = chunk.last() {
+            self.last_byte = last;
+        }
+        self.stats.bytes += chunk.len() as u64;
+        self.offset += chunk.len() as u64;
+    }
+
+    /// True when the line from field_start to end is empty or `\r` and has no delimiter.
+    fn is_blank_line(&self, end: u64, chunk: &[u8]) -> bool {
+        if self.delimiters > 0 {
+            return false;
+        }
+        match end - self.field_start {
+            0 => true,
+            1 if self.field_start >= self.offset => chunk[(self.field_start - self.offset) as usize] == b'\r',
+            // field_start is the last byte of the previous chunk.
+            1 => self.last_byte == b'\r',
+            _ => false,
+        }
+    }
+
+    fn end_record(&mut self) {
+        self.stats.records += 1;
+        *self.stats.fields.entry(self.delimiters + 1).or_insert(0) += 1;
+        self.delimiters = 0;
+        self.quote = Quote::None;
+    }
+
+    fn finish(mut self) -> Stats {
+        let end = self.offset;
+        if end > 0 && self.last_byte != b'\n' {
+            self.stats.lines += 1;
+        }
+        let blank = self.delimiters == 0
+            && (end == self.field_start || (end == self.field_start + 1 && self.last_byte == b'\r'));
+        if !blank {
+            self.end_record();
+        }
+        self.stats
+    }
+}
+
+/// Counts input read in chunks of buffer_size and searched with backend. A UTF-8 byte order mark is not part of the first field,
+/// and UTF-16 is transcoded to UTF-8 one chunk at a time. The bytes are those of input in both cases.
+fn count<R: Read>(mut input: R, name: &str, delimiter: u8, backend: Backend, buffer_size: usize) -> Result<Stats, Error> {
+    let mut counter = Counter::new(delimiter, backend);
+    let mut buffer = vec![0; buffer_size];
+    let mut filled = 0;
+    let mut detected = false;
+    // For UTF-16, the decoder and the UTF-8 of a chunk.
+    let mut utf16: Option<(Utf16Decoder, Vec<u8>)> = None;
+    let mut bytes = 0;
+    loop {
+        if filled == buffer.len() {
+            // Only before detection, when the buffer is smaller than a byte order mark.
+            buffer.resize(buffer.len() * 2, 0);
+        }
+        let n = read(&mut input, &mut buffer[filled..], name)?;
+        filled += n;
+        bytes += n as u64;
+        let mut start = 0;
+        if !detected {
+            // A byte order mark is only looked for once its bytes are there.
+            if filled < 3 && n > 0 {
+                continue;
+            }
+            detected = true;
+            let encoding = Encoding::detect(&buffer[..filled]);
+            start = encoding.bom_len();
+            if encoding.is_utf8() {
+                counter.skip(start);
+            }
+            else {
+                utf16 = Some((Utf16Decoder::new(encoding, start), Vec::new()));
+            }
+        }
+        match utf16 {
+            Some((ref mut decoder, ref mut utf8)) => {
+                utf8.clear();
+                decoder.decode(&buffer[start..filled], n == 0, utf8).map_err(|err| encoding_error(name, err))?;
+                counter.feed(utf8);
+            }
+            None => counter.feed(&buffer[start..filled]),
+        }
+        filled = 0;
+        if n == 0 {
+            let mut stats = counter.finish();
+            // The counter is fed the UTF-8 of UTF-16, so the bytes of input are counted here.
+            stats.bytes = bytes;
+            return Ok(stats);
+        }
+    }
+}
+
+fn print<W: Write>(out: &mut W, stats: &Stats, file: &str, json: bool) -> Result<(), Error> {
+    let summary = stats.fields_summary();
+    if json {
+        out.write_all(b"{\"file\":")?;
+        json_string(out, file.as_bytes())?;
+        write!(out, ",\"bytes\":{},\"lines\":{},\"records\":{},\"fields\":", stats.bytes, stats.lines, stats.records)?;
+        match summary {
+            Some((min, max, mode)) => writeln!(out, "{{\"min\":{},\"max\":{},\"mode\":{}}}}}", min, max, mode)?,
+            None => writeln!(out, "null}}")?,
+        }
+        return Ok(());
+    }
+    let fields = match summary {
+        Some((min, max, mode)) => format!("{}/{}/{}", min, max, mode),
+        None => "-".to_string(),
+    };
+    write!(out, "{}\t{}\t{}\t{}", stats.lines, stats.records, fields, stats.bytes)?;
+    if file != "-" {
+        write!(out, "\t{}", file)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Prints lines, quote aware csv records, fields per record (min/max/mode) and bytes of each input,
+/// and their total when there are several. The input is read in chunks of args.buffer_size.
+pub fn run<W: Write>(args: &Args, out: &mut W) -> Result<(), Error> {
+    let mut total = Stats::default();
+    for file in &args.files {
+        let stats = count(open_input(file)?, file, args.delimiter, args.backend, args.buffer_size)?;
+        print(out, &stats, file, args.json)?;
+        total.add(&stats);
+    }
+    if args.files.len() > 1 {
+        print(out, &total, "total", args.json)?;
+    }
+    Ok(())
+}
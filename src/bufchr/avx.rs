@@ -1,229 +1,169 @@
-use core::{arch::x86_64::*, cmp, mem::size_of};
-use crate::bufchr::fallback;
+use core::{arch::x86_64::*, mem::size_of};
+use crate::bufchr::vector::{self, Vector};
+use crate::bufchr::Utf8Scan;
 
 const VECTOR_SIZE: usize = size_of::<__m256i>();
-const LOOP_COUNT: usize = 2;
-const BATCH_BYTE_SIZE: usize = VECTOR_SIZE * LOOP_COUNT;
-const BATCH_BYTE_SIZE2: usize = VECTOR_SIZE * LOOP_COUNT * 2;
 
-const LINE_FEED_M256I:__m256i = unsafe {
-    std::mem::transmute::<[u32;8], __m256i>(
-        [0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A])
-};
+impl Vector for __m256i {
+    const BYTES: usize = VECTOR_SIZE;
 
-const DOUBLE_QUOTATION_M256I:__m256i = unsafe {
-    std::mem::transmute::<[u32;8], __m256i>(
-        [0x22222222, 0x22222222, 0x22222222, 0x22222222, 0x22222222, 0x22222222, 0x22222222, 0x22222222])
-};
-
-pub fn get_vector_size() -> usize {
-    VECTOR_SIZE
-}
+    #[inline(always)]
+    unsafe fn splat(byte: u8) -> __m256i {
+        _mm256_set1_epi8(byte as i8)
+    }
 
-#[target_feature(enable = "avx")]
-pub unsafe fn bufchr(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr(haystack, n1, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn zero() -> __m256i {
+        _mm256_setzero_si256()
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = haystack.as_ptr();
-    let vn1 = _mm256_set1_epi8(n1 as i8);
-
-    while ptr < vector_end_ptr{
-        let chunk1 = _mm256_loadu_si256(ptr as *const __m256i);
-        let chunk2 = _mm256_loadu_si256(ptr.add(VECTOR_SIZE) as *const __m256i);
-        let mask1 = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk1, vn1));
-        let mask2 = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk2, vn1));
-        if (mask1 | mask2) != 0 {
-            let umask = to_u64(mask1, mask2);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+
+    #[inline(always)]
+    unsafe fn load(ptr: *const u8) -> __m256i {
+        _mm256_loadu_si256(ptr as *const __m256i)
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        vector_end_ptr, haystack_len % BATCH_BYTE_SIZE);
-        
-    match fallback::bufchr_raw(rest_haystack, n1) {
-        Some(pos) => {
-            (Some(sub(ptr, start_ptr) + pos), 0)
-        }
-        None => { (None, 0)}
+    #[inline(always)]
+    unsafe fn cmpeq(self, other: __m256i) -> __m256i {
+        _mm256_cmpeq_epi8(self, other)
     }
-}
 
-#[target_feature(enable = "avx")]
-pub unsafe fn bufchr2(haystack: &[u8], n1: u8, n2: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr2(haystack, n1, n2, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn or(self, other: __m256i) -> __m256i {
+        _mm256_or_si256(self, other)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let vn1 = _mm256_set1_epi8(n1 as i8);
-    let vn2 = _mm256_set1_epi8(n2 as i8);
-
-    while ptr < vector_end_ptr{
-        let chunk = _mm256_loadu_si256(ptr as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let mask1 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2);
-
-        let chunk = _mm256_loadu_si256(ptr.add(VECTOR_SIZE) as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let mask2 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2);
-
-        if (mask1 | mask2) != 0 {
-            let umask = to_u64(mask1, mask2);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+
+    #[inline(always)]
+    unsafe fn and(self, other: __m256i) -> __m256i {
+        _mm256_and_si256(self, other)
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        vector_end_ptr, haystack_len % BATCH_BYTE_SIZE);
-        
-    match fallback::bufchr2_raw(rest_haystack, n1, n2) {
-        Some(pos) => {
-            (Some(sub(ptr, start_ptr) + pos), 0)
-        }
-        None => { (None, 0)}
+    #[inline(always)]
+    unsafe fn xor(self, other: __m256i) -> __m256i {
+        _mm256_xor_si256(self, other)
     }
-}
 
-#[target_feature(enable = "avx")]
-pub unsafe fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr3(haystack, n1, n2, n3, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn sub_bytes(self, other: __m256i) -> __m256i {
+        _mm256_sub_epi8(self, other)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let vn1 = _mm256_set1_epi8(n1 as i8);
-    let vn2 = _mm256_set1_epi8(n2 as i8);
-    let vn3 = _mm256_set1_epi8(n3 as i8);
-
-    while ptr < vector_end_ptr{
-        let chunk = _mm256_loadu_si256(ptr as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask1 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        let chunk = _mm256_loadu_si256(ptr.add(VECTOR_SIZE) as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask2 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        if (mask1 | mask2) != 0 {
-            let umask = to_u64(mask1, mask2);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+
+    #[inline(always)]
+    unsafe fn sub_sat(self, other: __m256i) -> __m256i {
+        _mm256_subs_epu8(self, other)
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        vector_end_ptr, haystack_len % BATCH_BYTE_SIZE);
-        
-    match fallback::bufchr3_raw(rest_haystack, n1, n2, n3) {
-        Some(pos) => {
-            (Some(sub(ptr, start_ptr) + pos), 0)
-        }
-        None => { (None, 0)}
+    #[inline(always)]
+    unsafe fn min_bytes(self, other: __m256i) -> __m256i {
+        _mm256_min_epu8(self, other)
     }
-}
 
+    #[inline(always)]
+    unsafe fn high_nibbles(self) -> __m256i {
+        // There is no byte shift, so the bits shifted in from the next byte are masked out.
+        _mm256_and_si256(_mm256_srli_epi16(self, 4), _mm256_set1_epi8(0x0F))
+    }
 
-#[target_feature(enable = "avx")]
-pub unsafe fn bufchr_csv(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE2 {
-        return fallback::bufchr_csv(haystack, n1, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn lookup16(self, table: &[u8; 16]) -> __m256i {
+        // shuffle_epi8 looks up each 128 bit lane on its own, so both lanes get the table.
+        let table = _mm256_broadcastsi128_si256(_mm_loadu_si128(table.as_ptr() as *const __m128i));
+        _mm256_shuffle_epi8(table, self)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let vn1 = _mm256_set1_epi8(n1 as i8);
-    let &vn2 = &LINE_FEED_M256I;
-    let &vn3 = &DOUBLE_QUOTATION_M256I;
-
-    while ptr < vector_end_ptr{
-        let chunk = _mm256_load_si256(ptr as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask1 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        let chunk = _mm256_load_si256(ptr.add(VECTOR_SIZE) as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask2 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        let chunk = _mm256_load_si256(ptr.add(VECTOR_SIZE * 2) as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask3 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        let chunk = _mm256_load_si256(ptr.add(VECTOR_SIZE * 3) as *const __m256i);
-        let eq1 = _mm256_cmpeq_epi8(chunk, vn1);
-        let eq2 = _mm256_cmpeq_epi8(chunk, vn2);
-        let eq3 = _mm256_cmpeq_epi8(chunk, vn3);
-        let mask4 = _mm256_movemask_epi8(eq1) | _mm256_movemask_epi8(eq2) | _mm256_movemask_epi8(eq3);
-
-        if (mask1 | mask2 ) != 0 {
-            let umask1 = to_u64(mask1, mask2);
-            let umask2 = to_u64(mask3, mask4);
-            let bit_pos = umask1.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask1 & (umask1 - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache, umask2);
-        }
-        else if(mask3 | mask4 ) != 0 {
-            let umask2 = to_u64(mask3, mask4);
-            let bit_pos = umask2.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask2 & (umask2 - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos + BATCH_BYTE_SIZE), 0, cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE2);
+
+    #[inline(always)]
+    unsafe fn movemask(self) -> u64 {
+        _mm256_movemask_epi8(self) as u32 as u64
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        vector_end_ptr, haystack_len % BATCH_BYTE_SIZE2);
-        
-    match fallback::bufchr_csv_raw(rest_haystack, n1) {
-        Some(pos) => {
-            (Some(sub(ptr, start_ptr) + pos), 0, 0)
-        }
-        None => { (None, 0, 0)}
+    #[inline(always)]
+    unsafe fn sum_bytes(self) -> usize {
+        let sum = _mm256_sad_epu8(self, _mm256_setzero_si256());
+        let lanes = core::mem::transmute::<__m256i, [u64; 4]>(sum);
+        lanes.iter().sum::<u64>() as usize
     }
 }
 
-#[inline]
-fn sub(a: *const u8, b: *const u8) -> usize {
-    debug_assert!(a >= b);
-    (a as usize) - (b as usize)
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr(haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m256i, 1, 1>(haystack, [n1]);
+    (position, cache)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr2(haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m256i, 2, 1>(haystack, [n1, n2]);
+    (position, cache)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m256i, 3, 1>(haystack, [n1, n2, n3]);
+    (position, cache)
+}
+
+/// Searches 128 bytes per batch and returns the masks of both 64 byte blocks.
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr_csv(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+    let (position, [cache1, cache2]) = vector::find::<__m256i, 3, 2>(haystack, [n1, n2, n3]);
+    (position, cache1, cache2)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr_utf8(haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+    let (position, [cache], scan) = vector::find_utf8::<__m256i, 1, 1>(haystack, [n1], carry);
+    (position, cache, scan)
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn bufchr_csv_utf8(haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+    let (position, [cache1, cache2], scan) = vector::find_utf8::<__m256i, 3, 2>(haystack, [n1, n2, n3], carry);
+    (position, cache1, cache2, scan)
+}
+
+/// Returns the mask of needles in the first 64 bytes of haystack.
+#[target_feature(enable = "avx2")]
+pub unsafe fn block_mask(haystack: &[u8], n1: u8) -> u64 {
+    vector::block_masks::<__m256i, 1>(haystack, [n1])[0]
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn block_mask2(haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+    vector::block_masks::<__m256i, 2>(haystack, [n1, n2])
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn block_mask3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+    vector::block_masks::<__m256i, 3>(haystack, [n1, n2, n3])
 }
 
-#[inline]
-fn to_u64(i1: i32, i2: i32) -> u64 {
-    let i1_byte = i1.to_be_bytes(); 
-    let u1 = u32::from_be_bytes(i1_byte) as u64;
-    let i2_byte = i2.to_be_bytes(); 
-    let u2 = u32::from_be_bytes(i2_byte) as u64;
-    u2 << 32 | u1
+/// Returns [quote, backslash, op, whitespace] masks of the first 64 bytes of haystack.
+/// op is one of `{}[]:,` and whitespace is one of space, `\t`, `\n`, `\r`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn json_block(haystack: &[u8]) -> [u64; 4] {
+    vector::json_block::<__m256i>(haystack)
 }
 
+#[target_feature(enable = "avx2")]
+pub unsafe fn count(haystack: &[u8], n1: u8) -> usize {
+    vector::count::<__m256i, 1>(haystack, [n1])
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn count2(haystack: &[u8], n1: u8, n2: u8) -> usize {
+    vector::count::<__m256i, 2>(haystack, [n1, n2])
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn count3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+    vector::count::<__m256i, 3>(haystack, [n1, n2, n3])
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn count_csv(haystack: &[u8], n1: u8) -> usize {
+    vector::count::<__m256i, 3>(haystack, [n1, b'\n', b'"'])
+}
+
+#[target_feature(enable = "avx2")]
+pub unsafe fn find_json_escape(haystack: &[u8]) -> Option<usize> {
+    vector::find_json_escape::<__m256i>(haystack)
+}
@@ -1,28 +1,193 @@
+use crate::bufchr::Utf8Scan;
 
-pub fn bufchr(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    (haystack.iter().position(|&b| b == n1), 0)
+// The iterators continue after the batch of a returned position, so like the simd kernels these kernels return
+// the needles after it in its batch as masks, and none when the batch is not whole.
+fn find_batch<F: Fn(u8) -> bool, const BLOCKS: usize>(haystack: &[u8], is_needle: F) -> (Option<usize>, [u64; BLOCKS]) {
+    let batch_byte_size = 64 * BLOCKS;
+    let mut masks = [0; BLOCKS];
+    let position = match haystack.iter().position(|&b| is_needle(b)) {
+        Some(position) => position,
+        None => return (None, masks),
+    };
+    let batch_start = position / batch_byte_size * batch_byte_size;
+    let batch_end = batch_start + batch_byte_size;
+    if batch_end <= haystack.len() {
+        for (i, &b) in haystack[..batch_end].iter().enumerate().skip(position + 1) {
+            if is_needle(b) {
+                let bit = i - batch_start;
+                masks[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+    }
+    (Some(position), masks)
+}
+
+pub fn bufchr(haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = find_batch(haystack, |b| b == n1);
+    (position, cache)
 }
 pub fn bufchr_raw(haystack: &[u8], n1: u8) -> Option<usize>{
     haystack.iter().position(|&b| b == n1)
 }
 
-pub fn bufchr2(haystack: &[u8], n1: u8, n2: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    (haystack.iter().position(|&b| b == n1 || b == n2), 0)
+pub fn bufchr2(haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = find_batch(haystack, |b| b == n1 || b == n2);
+    (position, cache)
 }
 pub fn bufchr2_raw(haystack: &[u8], n1: u8, n2: u8) -> Option<usize> {
     haystack.iter().position(|&b| b == n1 || b == n2)
 }
 
-pub fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    (haystack.iter().position(|&b| b == n1 || b == n2 || b == n3), 0)
+pub fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = find_batch(haystack, |b| b == n1 || b == n2 || b == n3);
+    (position, cache)
 }
 pub fn bufchr3_raw(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> Option<usize> {
     haystack.iter().position(|&b| b == n1 || b == n2 || b == n3)
 }
 
-pub fn bufchr_csv(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64, u64) {
-    (haystack.iter().position(|&b| b == n1 || b == b'\n' || b == b'"'), 0, 0)
+pub fn bufchr_csv(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+    let (position, [cache1, cache2]) = find_batch(haystack, |b| b == n1 || b == n2 || b == n3);
+    (position, cache1, cache2)
+}
+pub fn bufchr_utf8(haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+    let (position, [cache]) = find_batch(haystack, |b| b == n1);
+    (position, cache, utf8_scan::<1>(haystack, position, carry))
+}
+
+pub fn bufchr_csv_utf8(haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+    let (position, [cache1, cache2]) = find_batch(haystack, |b| b == n1 || b == n2 || b == n3);
+    (position, cache1, cache2, utf8_scan::<2>(haystack, position, carry))
+}
+
+// Checks what the iterators move past: haystack up to the end of the batch of position, or all of it.
+fn utf8_scan<const BLOCKS: usize>(haystack: &[u8], position: Option<usize>, carry: [u8; 3]) -> Utf8Scan {
+    let batch_byte_size = 64 * BLOCKS;
+    let checked = match position {
+        Some(position) => ((position / batch_byte_size + 1) * batch_byte_size).min(haystack.len()),
+        None => haystack.len(),
+    };
+    match utf8_error(carry, &haystack[..checked]) {
+        Some(offset) => Utf8Scan::Invalid(offset),
+        None => Utf8Scan::Valid(checked),
+    }
+}
+
+/// Returns the offset of the first UTF-8 error of data after carry, the 3 valid bytes before it.
+/// An error of a sequence that starts in carry is at 0, and a sequence may be unfinished at the end of data.
+pub fn utf8_error(carry: [u8; 3], data: &[u8]) -> Option<usize> {
+    // The sequence of the last lead in carry, when data has to finish it.
+    let lead = carry.iter().rposition(|&b| b & 0xC0 != 0x80).unwrap_or(0);
+    let sequence_len = match carry[lead] {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xFF => 4,
+        _ => 1,
+    };
+    let unfinished = &carry[lead..(lead + sequence_len).min(3)];
+    let missing = (lead + sequence_len).saturating_sub(3);
+    let mut start = 0;
+    if missing > 0 {
+        start = missing.min(data.len());
+        let mut sequence = [0; 4];
+        sequence[..unfinished.len()].copy_from_slice(unfinished);
+        sequence[unfinished.len()..unfinished.len() + start].copy_from_slice(&data[..start]);
+        match core::str::from_utf8(&sequence[..unfinished.len() + start]) {
+            Err(err) if err.error_len().is_some() || start == missing => return Some(0),
+            _ => {}
+        }
+    }
+    match core::str::from_utf8(&data[start..]) {
+        // A sequence unfinished at the end is finished by the bytes after data.
+        Err(err) if err.error_len().is_some() => Some(start + err.valid_up_to()),
+        _ => None,
+    }
 }
+
 pub fn bufchr_csv_raw(haystack: &[u8], n1: u8) -> Option<usize> {
     haystack.iter().position(|&b| b == n1 || b == b'\n' || b == b'"')
 }
+
+/// Position of the first byte that is one of needles. Used for short haystacks and tails by the simd kernels.
+#[inline]
+pub fn find_any<const N: usize>(haystack: &[u8], needles: &[u8; N]) -> Option<usize> {
+    haystack.iter().position(|b| needles.contains(b))
+}
+
+/// Number of bytes that are one of needles.
+#[inline]
+pub fn count_any<const N: usize>(haystack: &[u8], needles: &[u8; N]) -> usize {
+    haystack.iter().filter(|&b| needles.contains(b)).count()
+}
+
+pub fn count(haystack: &[u8], n1: u8) -> usize {
+    haystack.iter().filter(|&&b| b == n1).count()
+}
+
+pub fn count2(haystack: &[u8], n1: u8, n2: u8) -> usize {
+    haystack.iter().filter(|&&b| b == n1 || b == n2).count()
+}
+
+pub fn count3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+    haystack.iter().filter(|&&b| b == n1 || b == n2 || b == n3).count()
+}
+
+pub fn count_csv(haystack: &[u8], n1: u8) -> usize {
+    haystack.iter().filter(|&&b| b == n1 || b == b'\n' || b == b'"').count()
+}
+
+pub fn find_json_escape(haystack: &[u8]) -> Option<usize> {
+    haystack.iter().position(|&b| b == b'"' || b == b'\\' || b < 0x20)
+}
+
+pub fn block_mask(haystack: &[u8], n1: u8) -> u64 {
+    let mut mask = 0;
+    for (i, &b) in haystack.iter().take(64).enumerate() {
+        if b == n1 {
+            mask |= 1 << i;
+        }
+    }
+    mask
+}
+
+pub fn block_mask2(haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+    [block_mask(haystack, n1), block_mask(haystack, n2)]
+}
+
+pub fn block_mask3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+    [block_mask(haystack, n1), block_mask(haystack, n2), block_mask(haystack, n3)]
+}
+
+const JSON_QUOTE: u8 = 1;
+const JSON_BACKSLASH: u8 = 2;
+const JSON_OP: u8 = 4;
+const JSON_WHITESPACE: u8 = 8;
+
+const JSON_CLASS: [u8; 256] = {
+    let mut table = [0; 256];
+    table[b'"' as usize] = JSON_QUOTE;
+    table[b'\\' as usize] = JSON_BACKSLASH;
+    table[b'{' as usize] = JSON_OP;
+    table[b'}' as usize] = JSON_OP;
+    table[b'[' as usize] = JSON_OP;
+    table[b']' as usize] = JSON_OP;
+    table[b':' as usize] = JSON_OP;
+    table[b',' as usize] = JSON_OP;
+    table[b' ' as usize] = JSON_WHITESPACE;
+    table[b'\t' as usize] = JSON_WHITESPACE;
+    table[b'\n' as usize] = JSON_WHITESPACE;
+    table[b'\r' as usize] = JSON_WHITESPACE;
+    table
+};
+
+pub fn json_block(haystack: &[u8]) -> [u64; 4] {
+    let mut masks = [0; 4];
+    for (i, &b) in haystack.iter().take(64).enumerate() {
+        let class = JSON_CLASS[b as usize];
+        masks[0] |= ((class & JSON_QUOTE != 0) as u64) << i;
+        masks[1] |= ((class & JSON_BACKSLASH != 0) as u64) << i;
+        masks[2] |= ((class & JSON_OP != 0) as u64) << i;
+        masks[3] |= ((class & JSON_WHITESPACE != 0) as u64) << i;
+    }
+    masks
+}
@@ -1,75 +1,95 @@
+#![forbid(unsafe_code)]
+
 use crate::bufchr;
-use crate::bufchr::CbBufchr;
-use crate::bufchr::CbBufchr2;
-use crate::bufchr::CbBufchr3;
-use crate::bufchr::CbBufchrCSV;
+use crate::bufchr::{Backend, DefaultKernels, DynamicKernels, Kernels};
+use crate::bufchr::utf8::Utf8Validator;
+use crate::csv::CsvDialect;
 
-const VECTOR_SIZE: usize = 32;
 const BATCH_BYTE_SIZE: usize = 64;
 const BATCH_BYTE_SIZE2: usize = 128;
 
 /// struct used when there is only one needle
-pub struct Bufchr<'a> {
+pub struct Bufchr<'a, K: Kernels = DefaultKernels> {
     haystack: &'a [u8],
+    utf8: Utf8Validator<'a>,
     needle0: u8,
     position: usize,
     align_pos: usize,
     cache: u64,
-    vector_end_ptr: *const u8,
-    cb_bufchr: CbBufchr,
+    kernels: K,
 }
 impl<'a> Bufchr<'a> {
     /// Needle is what you are trying to find and the location you are looking for is haystack.
     #[inline]
     pub fn new(haystack: &[u8], needle0: u8) -> Bufchr<'_> {
-        let cb_bufchr = bufchr::get_cb_bufchr();
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-
-        Bufchr {haystack: haystack, needle0: needle0,
-            position: 0, cache: 0, cb_bufchr: cb_bufchr,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
-        }
-    }
-
-    #[doc(hidden)]
-    #[inline]
-    pub fn new_avx(haystack: &[u8], needle0: u8) -> Bufchr<'_> {
-        let cb_bufchr = bufchr::avx::bufchr;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-            Bufchr {haystack: haystack, needle0: needle0,
-                position: 0, cache: 0, cb_bufchr: cb_bufchr,
-                align_pos: 0 ,vector_end_ptr: vector_end_ptr,
-            }
+        Bufchr::with_kernels(haystack, needle0, bufchr::default_kernels())
+    }
+}
+impl<'a> Bufchr<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
+    #[inline]
+    pub fn with_backend(haystack: &[u8], needle0: u8, backend: Backend) -> Option<Bufchr<'_, DynamicKernels>> {
+        Some(Bufchr::with_kernels(haystack, needle0, bufchr::dynamic_kernels(backend)?))
+    }
+}
+impl<'a, K: Kernels> Bufchr<'a, K> {
+    /// Like `new`, but uses kernels, for the other modules of the crate that are generic over them.
+    #[inline]
+    pub(crate) fn with_kernels(haystack: &[u8], needle0: u8, kernels: K) -> Bufchr<'_, K> {
+        Bufchr {haystack, needle0, utf8: Utf8Validator::new(haystack),
+            position: 0, cache: 0, kernels,
+            align_pos: 0,
+        }
     }
 
-    #[doc(hidden)]
+    /// Writes the next needle positions to out and returns how many were written.
+    /// Cached masks are decoded as a whole, so it is faster than calling next() for every position.
+    /// 0 is returned when there are no more needles. haystack must be shorter than 4GB because positions are u32.
     #[inline]
-    pub fn new_sse2(haystack: &[u8], needle0: u8) -> Bufchr<'_> {
-        let cb_bufchr = bufchr::sse2::bufchr;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr {haystack: haystack, needle0: needle0,
-            position: 0, cache: 0, cb_bufchr: cb_bufchr,
-            align_pos: 0 , vector_end_ptr: vector_end_ptr,
+    pub fn fill_positions(&mut self, out: &mut [u32]) -> usize {
+        assert!(self.haystack.len() <= u32::MAX as usize);
+        let mut written = 0;
+        while written < out.len() {
+            if self.cache != 0 {
+                written += decode_mask(&mut self.cache, self.align_pos, &mut out[written..]);
+                self.position = out[written - 1] as usize + 1;
+                continue;
+            }
+            match self.next() {
+                Some(position) => {
+                    out[written] = position as u32;
+                    written += 1;
+                }
+                None => break,
+            }
         }
+        written
+    }
+
+    /// With validate, the bytes are also checked to be UTF-8, in the same pass as the search.
+    /// Call it before the first position. `get_str` and `utf8_error` tell the result.
+    #[inline]
+    pub fn validate_utf8(mut self, validate: bool) -> Self {
+        self.utf8.enable(validate);
+        self
+    }
+
+    /// `haystack[start..end]` as a str, when `validate_utf8` is set, the positions returned so far
+    /// have moved past end(or there are no more), and it is valid UTF-8 that starts and ends at character boundaries.
+    /// The bytes up to a returned position are always checked, so the text between two positions can be taken.
+    #[inline]
+    pub fn get_str(&self, start: usize, end: usize) -> Option<&'a str> {
+        self.utf8.get_str(start, end)
+    }
+
+    /// The offset of the first byte that is not valid UTF-8(like `core::str::Utf8Error::valid_up_to`),
+    /// once the positions returned have moved past it. None while `validate_utf8` is not set.
+    #[inline]
+    pub fn utf8_error(&self) -> Option<usize> {
+        self.utf8.error()
     }
 }
-impl<'a> Iterator for Bufchr<'a> {
+impl<'a, K: Kernels> Iterator for Bufchr<'a, K> {
     type Item = usize;
 
     /// The needle position is returned. If there is no needle, None is returned.
@@ -93,18 +113,17 @@ impl<'a> Iterator for Bufchr<'a> {
         else{
             align_pos = ( (self.position - 1) / BATCH_BYTE_SIZE + 1) * BATCH_BYTE_SIZE;
         }
-        let haystack_len = self.haystack.len() - align_pos;
-        let new_haystack;
-        unsafe{
-            let haystack = self.haystack.as_ptr().add(align_pos);
-            new_haystack = std::slice::from_raw_parts(haystack, haystack_len);
-        }
-        let position;
-        unsafe{
-            let (position_, cache_) = (self.cb_bufchr)(new_haystack, self.needle0, self.vector_end_ptr);
-            position = position_;
-            self.cache = cache_;
-        }
+        let new_haystack = &self.haystack[align_pos..];
+        let (position, cache) = if self.utf8.is_active() {
+            let carry = self.utf8.carry(align_pos);
+            let (position, cache, scan) = self.kernels.bufchr_utf8(new_haystack, self.needle0, carry);
+            self.utf8.update(align_pos, scan, position);
+            (position, cache)
+        }
+        else {
+            self.kernels.bufchr(new_haystack, self.needle0)
+        };
+        self.cache = cache;
         if let Some(pos) = position {
             let position = align_pos + pos;
             self.position = position + 1;
@@ -119,65 +138,63 @@ impl<'a> Iterator for Bufchr<'a> {
 }
 
 /// struct used when there are two needles
-pub struct Bufchr2<'a> {
+pub struct Bufchr2<'a, K: Kernels = DefaultKernels> {
     haystack: &'a [u8],
     needle0: u8,
     needle1: u8,
     position: usize,
     align_pos: usize,
     cache: u64,
-    vector_end_ptr: *const u8,
-    cb_bufchr2: CbBufchr2,
+    kernels: K,
 }
 impl<'a> Bufchr2<'a> {
     /// needle0, needle1 are what you are trying to find and the location you are looking for is haystack.
     #[inline]
     pub fn new(haystack: &[u8], needle0: u8, needle1: u8) -> Bufchr2<'_> {
-        let cb_bufchr2 = bufchr::get_cb_bufchr2();
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr2 {haystack: haystack, needle0: needle0, needle1: needle1,
-            position: 0, cache: 0, cb_bufchr2: cb_bufchr2,
-            align_pos: 0 , vector_end_ptr: vector_end_ptr,
+        let kernels = bufchr::default_kernels();
+        Bufchr2 {haystack, needle0, needle1,
+            position: 0, cache: 0, kernels,
+            align_pos: 0,
         }
     }
-
-    #[doc(hidden)]
+}
+impl<'a> Bufchr2<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
     #[inline]
-    pub fn new_avx(haystack: &[u8], needle0: u8, needle1: u8) -> Bufchr2<'_> {
-        let cb_bufchr2 = bufchr::avx::bufchr2;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr2 {haystack: haystack, needle0: needle0, needle1: needle1,
-            position: 0, cache: 0, cb_bufchr2: cb_bufchr2,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
-        }
+    pub fn with_backend(haystack: &[u8], needle0: u8, needle1: u8, backend: Backend) -> Option<Bufchr2<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(Bufchr2 {haystack, needle0, needle1,
+            position: 0, cache: 0, kernels,
+            align_pos: 0,
+        })
     }
-
+}
+impl<'a, K: Kernels> Bufchr2<'a, K> {
+    /// Writes the next needle positions to out and returns how many were written.
+    /// Cached masks are decoded as a whole, so it is faster than calling next() for every position.
+    /// 0 is returned when there are no more needles. haystack must be shorter than 4GB because positions are u32.
     #[inline]
-    pub fn new_sse2(haystack: &[u8], needle0: u8, needle1: u8) -> Bufchr2<'_> {
-        let cb_bufchr2 = bufchr::sse2::bufchr2;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr2 {haystack: haystack, needle0: needle0, needle1: needle1,
-            position: 0, cache: 0, cb_bufchr2: cb_bufchr2,
-            align_pos: 0 , vector_end_ptr: vector_end_ptr,
+    pub fn fill_positions(&mut self, out: &mut [u32]) -> usize {
+        assert!(self.haystack.len() <= u32::MAX as usize);
+        let mut written = 0;
+        while written < out.len() {
+            if self.cache != 0 {
+                written += decode_mask(&mut self.cache, self.align_pos, &mut out[written..]);
+                self.position = out[written - 1] as usize + 1;
+                continue;
+            }
+            match self.next() {
+                Some(position) => {
+                    out[written] = position as u32;
+                    written += 1;
+                }
+                None => break,
+            }
         }
+        written
     }
 }
-impl<'a> Iterator for Bufchr2<'a> {
+impl<'a, K: Kernels> Iterator for Bufchr2<'a, K> {
     type Item = usize;
 
     /// The needle position is returned. If there is no needle, None is returned.
@@ -201,19 +218,9 @@ impl<'a> Iterator for Bufchr2<'a> {
         else{
             align_pos = ( (self.position - 1) / BATCH_BYTE_SIZE + 1) * BATCH_BYTE_SIZE;
         }
-        let haystack_len = self.haystack.len() - align_pos;
-        let new_haystack;
-        unsafe{
-            let haystack = self.haystack.as_ptr().add(align_pos);
-            new_haystack = std::slice::from_raw_parts(haystack, haystack_len);
-        }
-        let position;
-        unsafe{
-            let (position_, cache_) = (self.cb_bufchr2)
-                (new_haystack, self.needle0, self.needle1, self.vector_end_ptr);
-            position = position_;
-            self.cache = cache_;
-        }
+        let new_haystack = &self.haystack[align_pos..];
+        let (position, cache) = self.kernels.bufchr2(new_haystack, self.needle0, self.needle1);
+        self.cache = cache;
         if let Some(pos) = position {
             let position = align_pos + pos;
             self.position = position + 1;
@@ -228,7 +235,7 @@ impl<'a> Iterator for Bufchr2<'a> {
 }
 
 //// struct used when there are three needles
-pub struct Bufchr3<'a> {
+pub struct Bufchr3<'a, K: Kernels = DefaultKernels> {
     haystack: &'a [u8],
     needle0: u8,
     needle1: u8,
@@ -236,59 +243,56 @@ pub struct Bufchr3<'a> {
     position: usize,
     align_pos: usize,
     cache: u64,
-    vector_end_ptr: *const u8,
-    cb_bufchr3: CbBufchr3,
+    kernels: K,
 }
 impl<'a> Bufchr3<'a> {
     /// needle0, needle1, needle2 are what you are trying to find and the location you are looking for is haystack.
     #[inline]
     pub fn new(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8) -> Bufchr3<'_> {
-        let cb_bufchr3 = bufchr::get_cb_bufchr3();
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr3 {haystack: haystack, needle0: needle0, needle1: needle1, needle2: needle2,
-            position: 0, cache: 0, cb_bufchr3: cb_bufchr3,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
+        let kernels = bufchr::default_kernels();
+        Bufchr3 {haystack, needle0, needle1, needle2,
+            position: 0, cache: 0, kernels,
+            align_pos: 0,
         }
     }
-
-    #[doc(hidden)]
+}
+impl<'a> Bufchr3<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
     #[inline]
-    pub fn new_avx(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8) -> Bufchr3<'_> {
-        let cb_bufchr3 = bufchr::avx::bufchr3;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr3 {haystack: haystack, needle0: needle0, needle1: needle1, needle2: needle2,
-            position: 0, cache: 0, cb_bufchr3: cb_bufchr3,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
-        }
+    pub fn with_backend(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8, backend: Backend) -> Option<Bufchr3<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(Bufchr3 {haystack, needle0, needle1, needle2,
+            position: 0, cache: 0, kernels,
+            align_pos: 0,
+        })
     }
-
-    #[doc(hidden)]
+}
+impl<'a, K: Kernels> Bufchr3<'a, K> {
+    /// Writes the next needle positions to out and returns how many were written.
+    /// Cached masks are decoded as a whole, so it is faster than calling next() for every position.
+    /// 0 is returned when there are no more needles. haystack must be shorter than 4GB because positions are u32.
     #[inline]
-    pub fn new_sse2(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8) -> Bufchr3<'_> {
-        let cb_bufchr3 = bufchr::sse2::bufchr3;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        Bufchr3 {haystack: haystack, needle0: needle0, needle1: needle1, needle2: needle2,
-            position: 0, cache: 0, cb_bufchr3: cb_bufchr3,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
+    pub fn fill_positions(&mut self, out: &mut [u32]) -> usize {
+        assert!(self.haystack.len() <= u32::MAX as usize);
+        let mut written = 0;
+        while written < out.len() {
+            if self.cache != 0 {
+                written += decode_mask(&mut self.cache, self.align_pos, &mut out[written..]);
+                self.position = out[written - 1] as usize + 1;
+                continue;
+            }
+            match self.next() {
+                Some(position) => {
+                    out[written] = position as u32;
+                    written += 1;
+                }
+                None => break,
+            }
         }
+        written
     }
 }
-impl<'a> Iterator for Bufchr3<'a> {
+impl<'a, K: Kernels> Iterator for Bufchr3<'a, K> {
     type Item = usize;
 
     /// The needle position is returned. If there is no needle, None is returned.
@@ -312,19 +316,9 @@ impl<'a> Iterator for Bufchr3<'a> {
         else{
             align_pos = ( (self.position - 1) / BATCH_BYTE_SIZE + 1) * BATCH_BYTE_SIZE;
         }
-        let haystack_len = self.haystack.len() - align_pos;
-        let new_haystack;
-        unsafe{
-            let haystack = self.haystack.as_ptr().add(align_pos);
-            new_haystack = std::slice::from_raw_parts(haystack, haystack_len);
-        }
-        let position;
-        unsafe{
-            let (position_, cache_) = (self.cb_bufchr3)(
-                new_haystack, self.needle0, self.needle1, self.needle2, self.vector_end_ptr);
-            position = position_;
-            self.cache = cache_;
-        }
+        let new_haystack = &self.haystack[align_pos..];
+        let (position, cache) = self.kernels.bufchr3(new_haystack, self.needle0, self.needle1, self.needle2);
+        self.cache = cache;
         if let Some(pos) = position {
             let position = align_pos + pos;
             self.position = position + 1;
@@ -338,66 +332,110 @@ impl<'a> Iterator for Bufchr3<'a> {
 
 }
 
-pub struct BufchrCSV<'a> {
+pub struct BufchrCSV<'a, K: Kernels = DefaultKernels> {
     haystack: &'a [u8],
+    utf8: Utf8Validator<'a>,
     needle0: u8,
+    line_end: u8,
+    quote: u8,
     position: usize,
     align_pos: usize,
     cache1: u64,
     cache2: u64,
-    vector_end_ptr: *const u8,
-    cb_bufchr_csv: CbBufchrCSV,
+    kernels: K,
 }
 impl<'a> BufchrCSV<'a> {
     /// needle0, needle1, needle2 are what you are trying to find and the location you are looking for is haystack.
     #[inline]
     pub fn new(haystack: &[u8], needle0: u8) -> BufchrCSV<'_> {
-        let cb_bufchr_csv = bufchr::get_cb_BufchrCSV();
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE2) * BATCH_BYTE_SIZE2)
-            };
-        BufchrCSV {haystack: haystack, needle0: needle0,
-            position: 0, cache1: 0, cache2: 0, cb_bufchr_csv: cb_bufchr_csv,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
+        let kernels = bufchr::default_kernels();
+        BufchrCSV {haystack, needle0, utf8: Utf8Validator::new(haystack), line_end: b'\n', quote: b'"',
+            position: 0, cache1: 0, cache2: 0, kernels,
+            align_pos: 0,
         }
     }
 
-    #[doc(hidden)]
+    /// Like `new`, but finds the delimiter, line end and quote of dialect, e.g. one returned by `csv::sniff`.
+    #[inline]
+    pub fn with_dialect(haystack: &[u8], dialect: CsvDialect) -> BufchrCSV<'_> {
+        BufchrCSV::with_kernels(haystack, dialect, bufchr::default_kernels())
+    }
+}
+impl<'a> BufchrCSV<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
     #[inline]
-    pub fn new_avx(haystack: &[u8], needle0: u8) -> BufchrCSV<'_> {
-        let cb_bufchr_csv = bufchr::avx::bufchr_csv;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        BufchrCSV {haystack: haystack, needle0: needle0,
-            position: 0, cache1: 0, cache2: 0, cb_bufchr_csv: cb_bufchr_csv,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
+    pub fn with_backend(haystack: &[u8], needle0: u8, backend: Backend) -> Option<BufchrCSV<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(BufchrCSV {haystack, needle0, utf8: Utf8Validator::new(haystack), line_end: b'\n', quote: b'"',
+            position: 0, cache1: 0, cache2: 0, kernels,
+            align_pos: 0,
+        })
+    }
+}
+impl<'a, K: Kernels> BufchrCSV<'a, K> {
+    /// Like `with_dialect`, but uses kernels, for the other modules of the crate that are generic over them.
+    #[inline]
+    pub(crate) fn with_kernels(haystack: &[u8], dialect: CsvDialect, kernels: K) -> BufchrCSV<'_, K> {
+        BufchrCSV {haystack, needle0: dialect.delimiter, utf8: Utf8Validator::new(haystack), line_end: dialect.line_end(), quote: dialect.quote,
+            position: 0, cache1: 0, cache2: 0, kernels,
+            align_pos: 0,
         }
     }
 
-    #[doc(hidden)]
+    /// Writes the next needle positions to out and returns how many were written.
+    /// Cached masks are decoded as a whole, so it is faster than calling next() for every position.
+    /// 0 is returned when there are no more needles. haystack must be shorter than 4GB because positions are u32.
     #[inline]
-    pub fn new_sse2(haystack: &[u8], needle0: u8) -> BufchrCSV<'_> {
-        let cb_bufchr_csv = bufchr::sse2::bufchr_csv;
-        let haystack_len = haystack.len();
-        let start_ptr = haystack.as_ptr();
-        let vector_end_ptr = 
-            unsafe{
-                start_ptr.add((haystack_len / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE)
-            };
-        BufchrCSV {haystack: haystack, needle0: needle0,
-            position: 0, cache1: 0, cache2: 0, cb_bufchr_csv: cb_bufchr_csv,
-            align_pos: 0, vector_end_ptr: vector_end_ptr,
+    pub fn fill_positions(&mut self, out: &mut [u32]) -> usize {
+        assert!(self.haystack.len() <= u32::MAX as usize);
+        let mut written = 0;
+        while written < out.len() {
+            if self.cache1 != 0 {
+                written += decode_mask(&mut self.cache1, self.align_pos, &mut out[written..]);
+                self.position = out[written - 1] as usize + 1;
+                continue;
+            }
+            else if self.cache2 != 0 {
+                written += decode_mask(
+                    &mut self.cache2, self.align_pos + BATCH_BYTE_SIZE, &mut out[written..]);
+                self.position = out[written - 1] as usize + 1;
+                continue;
+            }
+            match self.next() {
+                Some(position) => {
+                    out[written] = position as u32;
+                    written += 1;
+                }
+                None => break,
+            }
         }
+        written
+    }
+
+    /// With validate, the bytes are also checked to be UTF-8, in the same pass as the search.
+    /// Call it before the first position. `get_str` and `utf8_error` tell the result.
+    #[inline]
+    pub fn validate_utf8(mut self, validate: bool) -> Self {
+        self.utf8.enable(validate);
+        self
+    }
+
+    /// `haystack[start..end]` as a str, when `validate_utf8` is set, the positions returned so far
+    /// have moved past end(or there are no more), and it is valid UTF-8 that starts and ends at character boundaries.
+    /// The bytes up to a returned position are always checked, so the text between two positions can be taken.
+    #[inline]
+    pub fn get_str(&self, start: usize, end: usize) -> Option<&'a str> {
+        self.utf8.get_str(start, end)
+    }
+
+    /// The offset of the first byte that is not valid UTF-8(like `core::str::Utf8Error::valid_up_to`),
+    /// once the positions returned have moved past it. None while `validate_utf8` is not set.
+    #[inline]
+    pub fn utf8_error(&self) -> Option<usize> {
+        self.utf8.error()
     }
 }
-impl<'a> Iterator for BufchrCSV<'a> {
+impl<'a, K: Kernels> Iterator for BufchrCSV<'a, K> {
     type Item = usize;
 
     /// The needle position is returned. If there is no needle, None is returned.
@@ -429,27 +467,23 @@ impl<'a> Iterator for BufchrCSV<'a> {
         else{
             align_pos = ( (self.position - 1) / BATCH_BYTE_SIZE2 + 1) * BATCH_BYTE_SIZE2;
         }
-        let haystack_len = self.haystack.len() - align_pos;
-        let new_haystack;
-        unsafe{
-            let haystack = self.haystack.as_ptr().add(align_pos);
-            new_haystack = std::slice::from_raw_parts(haystack, haystack_len);
-        }
-        let position;
-        unsafe{
-            let (position_, cache1, cache2) = (self.cb_bufchr_csv)(
-                new_haystack, self.needle0, self.vector_end_ptr);
-            position = position_;
-            self.cache1 = cache1;
-            self.cache2 = cache2;
-        }
+        let new_haystack = &self.haystack[align_pos..];
+        let (position, cache1, cache2) = if self.utf8.is_active() {
+            let carry = self.utf8.carry(align_pos);
+            let (position, cache1, cache2, scan) =
+                self.kernels.bufchr_csv_utf8(new_haystack, self.needle0, self.line_end, self.quote, carry);
+            self.utf8.update(align_pos, scan, position);
+            (position, cache1, cache2)
+        }
+        else {
+            self.kernels.bufchr_csv(new_haystack, self.needle0, self.line_end, self.quote)
+        };
+        self.cache1 = cache1;
+        self.cache2 = cache2;
         if let Some(pos) = position {
             let position = align_pos + pos;
             self.position = position + 1;
-            if self.cache1 != 0 {
-                self.align_pos = get_align_pos_fast(position);
-            }
-            else if self.cache2 != 0 {
+            if self.cache1 != 0 || self.cache2 != 0 {
                 self.align_pos = get_align_pos_fast(position);
             }
             return Some(position);
@@ -459,6 +493,162 @@ impl<'a> Iterator for BufchrCSV<'a> {
 
 }
 
+/// struct that yields the needle mask of every 64 byte block.
+/// Bit i of the mask is set when `haystack[block_offset + i]` is the needle.
+pub struct BlockMasks<'a, K: Kernels = DefaultKernels> {
+    haystack: &'a [u8],
+    needle0: u8,
+    position: usize,
+    kernels: K,
+}
+impl<'a> BlockMasks<'a> {
+    /// Needle is what you are trying to find and the location you are looking for is haystack.
+    #[inline]
+    pub fn new(haystack: &[u8], needle0: u8) -> BlockMasks<'_> {
+        let kernels = bufchr::default_kernels();
+        BlockMasks {haystack, needle0,
+            position: 0, kernels,
+        }
+    }
+}
+impl<'a> BlockMasks<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
+    #[inline]
+    pub fn with_backend(haystack: &[u8], needle0: u8, backend: Backend) -> Option<BlockMasks<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(BlockMasks {haystack, needle0,
+            position: 0, kernels,
+        })
+    }
+}
+impl<'a, K: Kernels> Iterator for BlockMasks<'a, K> {
+    type Item = (usize, u64);
+
+    /// (block_offset, mask) of the next 64 byte block is returned. The last block may be shorter than 64 bytes.
+    #[inline]
+    fn next(&mut self) -> Option<(usize, u64)> {
+        if self.position >= self.haystack.len() {
+            return None;
+        }
+        let block_offset = self.position;
+        let block = &self.haystack[block_offset..];
+        let mask = if block.len() >= BATCH_BYTE_SIZE {
+            self.kernels.block_mask(block, self.needle0)
+        }
+        else {
+            bufchr::fallback::block_mask(block, self.needle0)
+        };
+        self.position += BATCH_BYTE_SIZE;
+        Some((block_offset, mask))
+    }
+
+}
+
+/// struct that yields the masks of two needles for every 64 byte block.
+/// masks[0] is the mask of needle0 and masks[1] is the mask of needle1.
+pub struct BlockMasks2<'a, K: Kernels = DefaultKernels> {
+    haystack: &'a [u8],
+    needle0: u8,
+    needle1: u8,
+    position: usize,
+    kernels: K,
+}
+impl<'a> BlockMasks2<'a> {
+    /// needle0, needle1 are what you are trying to find and the location you are looking for is haystack.
+    #[inline]
+    pub fn new(haystack: &[u8], needle0: u8, needle1: u8) -> BlockMasks2<'_> {
+        let kernels = bufchr::default_kernels();
+        BlockMasks2 {haystack, needle0, needle1,
+            position: 0, kernels,
+        }
+    }
+}
+impl<'a> BlockMasks2<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
+    #[inline]
+    pub fn with_backend(haystack: &[u8], needle0: u8, needle1: u8, backend: Backend) -> Option<BlockMasks2<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(BlockMasks2 {haystack, needle0, needle1,
+            position: 0, kernels,
+        })
+    }
+}
+impl<'a, K: Kernels> Iterator for BlockMasks2<'a, K> {
+    type Item = (usize, [u64; 2]);
+
+    /// (block_offset, mask) of the next 64 byte block is returned. The last block may be shorter than 64 bytes.
+    #[inline]
+    fn next(&mut self) -> Option<(usize, [u64; 2])> {
+        if self.position >= self.haystack.len() {
+            return None;
+        }
+        let block_offset = self.position;
+        let block = &self.haystack[block_offset..];
+        let mask = if block.len() >= BATCH_BYTE_SIZE {
+            self.kernels.block_mask2(block, self.needle0, self.needle1)
+        }
+        else {
+            bufchr::fallback::block_mask2(block, self.needle0, self.needle1)
+        };
+        self.position += BATCH_BYTE_SIZE;
+        Some((block_offset, mask))
+    }
+
+}
+
+/// struct that yields the masks of three needles for every 64 byte block.
+/// masks[0], masks[1], masks[2] are the masks of needle0, needle1, needle2.
+pub struct BlockMasks3<'a, K: Kernels = DefaultKernels> {
+    haystack: &'a [u8],
+    needle0: u8,
+    needle1: u8,
+    needle2: u8,
+    position: usize,
+    kernels: K,
+}
+impl<'a> BlockMasks3<'a> {
+    /// needle0, needle1, needle2 are what you are trying to find and the location you are looking for is haystack.
+    #[inline]
+    pub fn new(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8) -> BlockMasks3<'_> {
+        let kernels = bufchr::default_kernels();
+        BlockMasks3 {haystack, needle0, needle1, needle2,
+            position: 0, kernels,
+        }
+    }
+}
+impl<'a> BlockMasks3<'a, DynamicKernels> {
+    /// Like `new`, but uses the kernels of backend. None is returned when backend is not available.
+    #[inline]
+    pub fn with_backend(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8, backend: Backend) -> Option<BlockMasks3<'_, DynamicKernels>> {
+        let kernels = bufchr::dynamic_kernels(backend)?;
+        Some(BlockMasks3 {haystack, needle0, needle1, needle2,
+            position: 0, kernels,
+        })
+    }
+}
+impl<'a, K: Kernels> Iterator for BlockMasks3<'a, K> {
+    type Item = (usize, [u64; 3]);
+
+    /// (block_offset, mask) of the next 64 byte block is returned. The last block may be shorter than 64 bytes.
+    #[inline]
+    fn next(&mut self) -> Option<(usize, [u64; 3])> {
+        if self.position >= self.haystack.len() {
+            return None;
+        }
+        let block_offset = self.position;
+        let block = &self.haystack[block_offset..];
+        let mask = if block.len() >= BATCH_BYTE_SIZE {
+            self.kernels.block_mask3(block, self.needle0, self.needle1, self.needle2)
+        }
+        else {
+            bufchr::fallback::block_mask3(block, self.needle0, self.needle1, self.needle2)
+        };
+        self.position += BATCH_BYTE_SIZE;
+        Some((block_offset, mask))
+    }
+
+}
+
 #[inline(always)]
 fn get_align_pos(position: usize) -> usize {
     (position / BATCH_BYTE_SIZE) * BATCH_BYTE_SIZE
@@ -468,3 +658,39 @@ fn get_align_pos_fast(position: usize) -> usize {
     (position / BATCH_BYTE_SIZE2) * BATCH_BYTE_SIZE2
 }
 
+/// Decodes the set bits of mask into out as positions from align_pos.
+/// Bits that do not fit in out are left in mask.
+#[inline(always)]
+fn decode_mask(mask: &mut u64, align_pos: usize, out: &mut [u32]) -> usize {
+    let align_pos = align_pos as u32;
+    let mut bits = *mask;
+    let count = bits.count_ones() as usize;
+    if count > out.len() {
+        let written = out.len();
+        for o in out.iter_mut() {
+            *o = align_pos + bits.trailing_zeros();
+            bits &= bits - 1;
+        }
+        *mask = bits;
+        return written;
+    }
+    let mut idx = 0;
+    while idx + 4 <= count {
+        out[idx] = align_pos + bits.trailing_zeros();
+        bits &= bits - 1;
+        out[idx + 1] = align_pos + bits.trailing_zeros();
+        bits &= bits - 1;
+        out[idx + 2] = align_pos + bits.trailing_zeros();
+        bits &= bits - 1;
+        out[idx + 3] = align_pos + bits.trailing_zeros();
+        bits &= bits - 1;
+        idx += 4;
+    }
+    while bits != 0 {
+        out[idx] = align_pos + bits.trailing_zeros();
+        bits &= bits - 1;
+        idx += 1;
+    }
+    *mask = 0;
+    count
+}
@@ -1,81 +1,648 @@
-#![feature(target_feature)]
+use core::fmt;
+#[cfg(not(target_feature = "avx2"))]
+use core::ptr;
+use core::str::FromStr;
+#[cfg(not(target_feature = "avx2"))]
+use core::sync::atomic::{AtomicPtr, Ordering};
 
 #[doc(hidden)]
-pub use self::iter::{Bufchr, Bufchr2, Bufchr3, BufchrCSV};
+pub use self::iter::{Bufchr, Bufchr2, Bufchr3, BufchrCSV, BlockMasks, BlockMasks2, BlockMasks3};
 
 #[doc(hidden)]
 pub mod iter;
+// Soft float targets such as x86_64-unknown-none have no vector registers, so only the fallback is built there.
+#[cfg(target_feature = "sse2")]
 #[doc(hidden)]
 pub mod avx;
+#[cfg(target_feature = "sse2")]
 #[doc(hidden)]
 pub mod sse2;
 #[doc(hidden)]
 pub mod fallback;
+#[cfg(feature = "portable_simd")]
+#[doc(hidden)]
+pub mod portable;
+#[cfg(any(target_feature = "sse2", feature = "portable_simd"))]
+mod vector;
+mod utf8;
 
 #[doc(hidden)]
-pub type CbBufchr = unsafe fn(haystack: &[u8], n1: u8,  *const u8) -> (Option<usize>, u64);
+pub type CbBufchr = unsafe fn(haystack: &[u8], n1: u8) -> (Option<usize>, u64);
 #[doc(hidden)]
-pub type CbBufchr2 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, *const u8) -> (Option<usize>, u64);
+pub type CbBufchr2 = unsafe fn(haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64);
 #[doc(hidden)]
-pub type CbBufchr3 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3:u8, *const u8) -> (Option<usize>, u64);
+pub type CbBufchr3 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3:u8) -> (Option<usize>, u64);
 #[doc(hidden)]
-pub type CbBufchrCSV = unsafe fn(haystack: &[u8], n1: u8, *const u8) -> (Option<usize>, u64, u64);
-
+pub type CbBufchrCSV = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64);
+#[doc(hidden)]
+pub type CbBufchrUtf8 = unsafe fn(haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan);
+#[doc(hidden)]
+pub type CbBufchrCSVUtf8 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan);
+#[doc(hidden)]
+pub type CbBlockMask = unsafe fn(haystack: &[u8], n1: u8) -> u64;
+#[doc(hidden)]
+pub type CbBlockMask2 = unsafe fn(haystack: &[u8], n1: u8, n2: u8) -> [u64; 2];
+#[doc(hidden)]
+pub type CbBlockMask3 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3];
+#[doc(hidden)]
+pub type CbJsonBlock = unsafe fn(haystack: &[u8]) -> [u64; 4];
+#[doc(hidden)]
+pub type CbCount = unsafe fn(haystack: &[u8], n1: u8) -> usize;
+#[doc(hidden)]
+pub type CbCount2 = unsafe fn(haystack: &[u8], n1: u8, n2: u8) -> usize;
+#[doc(hidden)]
+pub type CbCount3 = unsafe fn(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize;
+#[doc(hidden)]
+pub type CbCountCSV = unsafe fn(haystack: &[u8], n1: u8) -> usize;
+#[doc(hidden)]
+pub type CbFindJsonEscape = unsafe fn(haystack: &[u8]) -> Option<usize>;
 
+/// How much of its haystack a `_utf8` search kernel checked.
 #[doc(hidden)]
-pub fn get_cb_bufchr() -> CbBufchr{
-    if is_x86_feature_detected!("avx2"){
-        unsafe{
-            return avx::bufchr;
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Utf8Scan {
+    /// The first n bytes are valid UTF-8, but the last sequence may be unfinished.
+    Valid(usize),
+    /// The bytes before this offset are valid, and there is an error after it or in a sequence that starts before it.
+    Invalid(usize),
+}
+
+/// Environment variable read at the first dispatch to force a backend, e.g. `BUFCHR_FORCE_BACKEND=sse2`.
+/// It is ignored when avx2 is enabled at compile time, because the avx2 kernels are then called directly.
+pub const FORCE_BACKEND_ENV: &str = "BUFCHR_FORCE_BACKEND";
+
+/// The set of kernels used to search.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// 64 byte vectors. Reserved, there are no avx512 kernels yet so it is never available.
+    Avx512,
+    /// 32 byte vectors.
+    Avx2,
+    /// 16 byte vectors.
+    Sse2,
+    /// 16 byte vectors on aarch64. Reserved, there are no neon kernels yet so it is never available.
+    Neon,
+    /// 64 byte `core::simd` vectors, lowered to whatever the target has.
+    /// Available with the nightly `portable_simd` feature.
+    Portable,
+    /// Byte by byte search. Always available.
+    Scalar,
+}
+
+impl Backend {
+    /// Returns true when the kernels of this backend exist and the cpu supports them.
+    pub fn is_available(self) -> bool {
+        dispatch_for(self).is_some()
     }
-    else if is_x86_feature_detected!("sse2") {
-        unsafe{
-            return sse2::bufchr;
+
+    /// The lowercase name, as accepted by `FORCE_BACKEND_ENV`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Avx512 => "avx512",
+            Backend::Avx2 => "avx2",
+            Backend::Sse2 => "sse2",
+            Backend::Neon => "neon",
+            Backend::Portable => "portable",
+            Backend::Scalar => "scalar",
         }
     }
-    fallback::bufchr
 }
 
-#[doc(hidden)]
-pub fn get_cb_bufchr2() -> CbBufchr2{
-    if is_x86_feature_detected!("avx2"){
-        unsafe{
-            return avx::bufchr2;
-        }
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
     }
-    else if is_x86_feature_detected!("sse2") {
-        unsafe{
-            return sse2::bufchr2;
-        }
+}
+
+/// The name is not one of avx512, avx2, sse2, neon, portable or scalar.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseBackendError(());
+
+impl fmt::Display for ParseBackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown backend (expected avx512, avx2, sse2, neon, portable or scalar)")
     }
-    fallback::bufchr2
 }
 
-pub fn get_cb_bufchr3() -> CbBufchr3{
-    if is_x86_feature_detected!("avx2"){
-        unsafe{
-            return avx::bufchr3;
-        }
+#[cfg(feature = "std")]
+impl std::error::Error for ParseBackendError {}
+
+impl FromStr for Backend {
+    type Err = ParseBackendError;
+
+    /// Case insensitive. `fallback` is accepted as an alias of scalar.
+    fn from_str(s: &str) -> Result<Backend, ParseBackendError> {
+        let s = s.trim();
+        let names: [(&str, Backend); 8] = [
+            ("avx512", Backend::Avx512), ("avx2", Backend::Avx2), ("avx", Backend::Avx2),
+            ("sse2", Backend::Sse2), ("neon", Backend::Neon), ("portable", Backend::Portable),
+            ("scalar", Backend::Scalar), ("fallback", Backend::Scalar),
+        ];
+        names.iter()
+            .find(|(name, _)| s.eq_ignore_ascii_case(name))
+            .map(|&(_, backend)| backend)
+            .ok_or(ParseBackendError(()))
     }
-    else if is_x86_feature_detected!("sse2") {
-        unsafe{
-            return sse2::bufchr3;
-        }
+}
+
+/// Returns the backend used by `new` constructors and the count functions.
+///
+/// ```
+/// let backend = bufchr::active_backend();
+/// assert!(backend.is_available());
+/// ```
+pub fn active_backend() -> Backend {
+    default_kernels().backend()
+}
+
+// Contract of every kernel in avx.rs, sse2.rs and fallback.rs:
+// * it reads only bytes inside the haystack slice it is given, for any length and any alignment.
+// * returned positions are relative to the start of that slice.
+// * the search kernels return the position of the first needle and a cache mask whose bit i is a needle
+//   at `get_align_pos(position) + i` (for bufchr_csv, the second mask is for the next 64 bytes).
+// * the _utf8 search kernels also check that the bytes they search are UTF-8 after the 3 bytes of carry, see Utf8Scan.
+//   They check up to the end of the batch of the returned position, like the masks, and may leave the tail to the caller.
+// * an avx2 or sse2 kernel may only run on a cpu that supports it. This is the only reason they are unsafe.
+// Kernels check the loop bounds with debug_assert!.
+
+/// Kernels of one backend. The backend is selected once per process by `dispatch`.
+pub(crate) struct Dispatch {
+    backend: Backend,
+    bufchr: CbBufchr,
+    bufchr2: CbBufchr2,
+    bufchr3: CbBufchr3,
+    bufchr_csv: CbBufchrCSV,
+    bufchr_utf8: CbBufchrUtf8,
+    bufchr_csv_utf8: CbBufchrCSVUtf8,
+    block_mask: CbBlockMask,
+    block_mask2: CbBlockMask2,
+    block_mask3: CbBlockMask3,
+    #[cfg_attr(not(feature = "alloc"), allow(dead_code))]
+    json_block: CbJsonBlock,
+    count: CbCount,
+    count2: CbCount2,
+    count3: CbCount3,
+    count_csv: CbCountCSV,
+    find_json_escape: CbFindJsonEscape,
+}
+
+#[cfg(target_feature = "sse2")]
+static AVX2_DISPATCH: Dispatch = Dispatch {
+    backend: Backend::Avx2,
+    bufchr: avx::bufchr,
+    bufchr2: avx::bufchr2,
+    bufchr3: avx::bufchr3,
+    bufchr_csv: avx::bufchr_csv,
+    bufchr_utf8: avx::bufchr_utf8,
+    bufchr_csv_utf8: avx::bufchr_csv_utf8,
+    block_mask: avx::block_mask,
+    block_mask2: avx::block_mask2,
+    block_mask3: avx::block_mask3,
+    json_block: avx::json_block,
+    count: avx::count,
+    count2: avx::count2,
+    count3: avx::count3,
+    count_csv: avx::count_csv,
+    find_json_escape: avx::find_json_escape,
+};
+
+#[cfg(target_feature = "sse2")]
+static SSE2_DISPATCH: Dispatch = Dispatch {
+    backend: Backend::Sse2,
+    bufchr: sse2::bufchr,
+    bufchr2: sse2::bufchr2,
+    bufchr3: sse2::bufchr3,
+    bufchr_csv: sse2::bufchr_csv,
+    bufchr_utf8: sse2::bufchr_utf8,
+    bufchr_csv_utf8: sse2::bufchr_csv_utf8,
+    block_mask: sse2::block_mask,
+    block_mask2: sse2::block_mask2,
+    block_mask3: sse2::block_mask3,
+    json_block: sse2::json_block,
+    count: sse2::count,
+    count2: sse2::count2,
+    count3: sse2::count3,
+    count_csv: sse2::count_csv,
+    find_json_escape: sse2::find_json_escape,
+};
+
+#[cfg(feature = "portable_simd")]
+static PORTABLE_DISPATCH: Dispatch = Dispatch {
+    backend: Backend::Portable,
+    bufchr: portable::bufchr,
+    bufchr2: portable::bufchr2,
+    bufchr3: portable::bufchr3,
+    bufchr_csv: portable::bufchr_csv,
+    bufchr_utf8: portable::bufchr_utf8,
+    bufchr_csv_utf8: portable::bufchr_csv_utf8,
+    block_mask: portable::block_mask,
+    block_mask2: portable::block_mask2,
+    block_mask3: portable::block_mask3,
+    json_block: portable::json_block,
+    count: portable::count,
+    count2: portable::count2,
+    count3: portable::count3,
+    count_csv: portable::count_csv,
+    find_json_escape: portable::find_json_escape,
+};
+
+static FALLBACK_DISPATCH: Dispatch = Dispatch {
+    backend: Backend::Scalar,
+    bufchr: fallback::bufchr,
+    bufchr2: fallback::bufchr2,
+    bufchr3: fallback::bufchr3,
+    bufchr_csv: fallback::bufchr_csv,
+    bufchr_utf8: fallback::bufchr_utf8,
+    bufchr_csv_utf8: fallback::bufchr_csv_utf8,
+    block_mask: fallback::block_mask,
+    block_mask2: fallback::block_mask2,
+    block_mask3: fallback::block_mask3,
+    json_block: fallback::json_block,
+    count: fallback::count,
+    count2: fallback::count2,
+    count3: fallback::count3,
+    count_csv: fallback::count_csv,
+    find_json_escape: fallback::find_json_escape,
+};
+
+// With avx2 enabled at compile time the kernels are called directly and nothing is detected.
+#[cfg(not(target_feature = "avx2"))]
+static DISPATCH: AtomicPtr<Dispatch> = AtomicPtr::new(ptr::null_mut());
+
+/// Returns the kernels of the selected backend.
+/// cpu features are detected on the first call and the selected table is cached in DISPATCH,
+/// like the ifunc pattern of memchr. If several threads race on the first call, they store the same table.
+#[cfg(not(target_feature = "avx2"))]
+#[inline]
+pub(crate) fn dispatch() -> &'static Dispatch {
+    let table = DISPATCH.load(Ordering::Relaxed);
+    if !table.is_null() {
+        return unsafe{ &*table };
     }
-    fallback::bufchr3
+    let table = detect();
+    DISPATCH.store(table as *const Dispatch as *mut Dispatch, Ordering::Relaxed);
+    table
 }
 
-pub fn get_cb_BufchrCSV() -> CbBufchrCSV{
-    if is_x86_feature_detected!("avx2"){
-        unsafe{
-            return avx::bufchr_csv;
+/// The backend named by `FORCE_BACKEND_ENV` is used when it is available,
+/// otherwise the best backend this cpu supports. A forced backend never runs on a cpu without it.
+/// Without the `std` feature there is no environment, so only the best backend is selected.
+#[cfg(not(target_feature = "avx2"))]
+#[cold]
+fn detect() -> &'static Dispatch {
+    #[cfg(feature = "std")]
+    {
+        let forced = std::env::var(FORCE_BACKEND_ENV).ok()
+            .and_then(|name| name.parse::<Backend>().ok())
+            .and_then(dispatch_for);
+        if let Some(table) = forced {
+            return table;
         }
     }
-    else if is_x86_feature_detected!("sse2") {
-        unsafe{
-            return sse2::bufchr_csv;
-        }
+    // The portable backend is only the default where there is no hand written one.
+    dispatch_for(Backend::Avx2)
+        .or_else(|| dispatch_for(Backend::Sse2))
+        .or_else(|| dispatch_for(Backend::Portable))
+        .unwrap_or(&FALLBACK_DISPATCH)
+}
+
+// With std, cpu features are detected at runtime.
+// Without std there is no cpuid cache, so only the features enabled at compile time(`-C target-feature`) are used.
+#[cfg(feature = "std")]
+#[cfg_attr(not(target_feature = "sse2"), allow(unused_macros))]
+macro_rules! has_cpu_feature {
+    ($feature:tt) => { is_x86_feature_detected!($feature) };
+}
+#[cfg(not(feature = "std"))]
+#[cfg_attr(not(target_feature = "sse2"), allow(unused_macros))]
+macro_rules! has_cpu_feature {
+    ($feature:tt) => { cfg!(target_feature = $feature) };
+}
+
+/// Returns the kernels of backend, or None when they do not exist or the cpu does not support them.
+pub(crate) fn dispatch_for(backend: Backend) -> Option<&'static Dispatch> {
+    match backend {
+        #[cfg(target_feature = "sse2")]
+        Backend::Avx2 if has_cpu_feature!("avx2") => Some(&AVX2_DISPATCH),
+        #[cfg(target_feature = "sse2")]
+        Backend::Sse2 if has_cpu_feature!("sse2") => Some(&SSE2_DISPATCH),
+        #[cfg(feature = "portable_simd")]
+        Backend::Portable => Some(&PORTABLE_DISPATCH),
+        Backend::Scalar => Some(&FALLBACK_DISPATCH),
+        _ => None,
     }
-    fallback::bufchr_csv
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Kernels of one backend, called by the iterators.
+/// Every kernel follows the contract above and a value of an implementing type only exists
+/// when its backend is supported, so the methods are safe.
+/// The iterators are generic over this trait, so a statically known backend is inlined into `next()`.
+pub trait Kernels: Copy + private::Sealed {
+    /// The backend whose kernels are called.
+    fn backend(self) -> Backend;
+    #[doc(hidden)]
+    fn bufchr(self, haystack: &[u8], n1: u8) -> (Option<usize>, u64);
+    #[doc(hidden)]
+    fn bufchr2(self, haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64);
+    #[doc(hidden)]
+    fn bufchr3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64);
+    #[doc(hidden)]
+    fn bufchr_csv(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64);
+    #[doc(hidden)]
+    fn bufchr_utf8(self, haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan);
+    #[doc(hidden)]
+    fn bufchr_csv_utf8(self, haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan);
+    #[doc(hidden)]
+    fn block_mask(self, haystack: &[u8], n1: u8) -> u64;
+    #[doc(hidden)]
+    fn block_mask2(self, haystack: &[u8], n1: u8, n2: u8) -> [u64; 2];
+    #[doc(hidden)]
+    fn block_mask3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3];
+    #[doc(hidden)]
+    fn json_block(self, haystack: &[u8]) -> [u64; 4];
+    #[doc(hidden)]
+    fn count(self, haystack: &[u8], n1: u8) -> usize;
+    #[doc(hidden)]
+    fn count2(self, haystack: &[u8], n1: u8, n2: u8) -> usize;
+    #[doc(hidden)]
+    fn count3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize;
+    #[doc(hidden)]
+    fn count_csv(self, haystack: &[u8], n1: u8) -> usize;
+    #[doc(hidden)]
+    fn find_json_escape(self, haystack: &[u8]) -> Option<usize>;
+}
+
+/// Kernels selected at runtime, called through the function pointers of a dispatch table.
+#[derive(Clone, Copy)]
+pub struct DynamicKernels(&'static Dispatch);
+
+impl private::Sealed for DynamicKernels {}
+
+// A `&'static Dispatch` only exists for a backend the cpu supports,
+// and every kernel is sound for any haystack (see the contract above), so these calls are safe.
+impl Kernels for DynamicKernels {
+    #[inline]
+    fn backend(self) -> Backend {
+        self.0.backend
+    }
+
+    #[inline]
+    fn bufchr(self, haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+        unsafe{ (self.0.bufchr)(haystack, n1) }
+    }
+
+    #[inline]
+    fn bufchr2(self, haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+        unsafe{ (self.0.bufchr2)(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn bufchr3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+        unsafe{ (self.0.bufchr3)(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn bufchr_csv(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+        unsafe{ (self.0.bufchr_csv)(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn bufchr_utf8(self, haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+        unsafe{ (self.0.bufchr_utf8)(haystack, n1, carry) }
+    }
+
+    #[inline]
+    fn bufchr_csv_utf8(self, haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+        unsafe{ (self.0.bufchr_csv_utf8)(haystack, n1, n2, n3, carry) }
+    }
+
+    #[inline]
+    fn block_mask(self, haystack: &[u8], n1: u8) -> u64 {
+        unsafe{ (self.0.block_mask)(haystack, n1) }
+    }
+
+    #[inline]
+    fn block_mask2(self, haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+        unsafe{ (self.0.block_mask2)(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn block_mask3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+        unsafe{ (self.0.block_mask3)(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn json_block(self, haystack: &[u8]) -> [u64; 4] {
+        unsafe{ (self.0.json_block)(haystack) }
+    }
+
+    #[inline]
+    fn count(self, haystack: &[u8], n1: u8) -> usize {
+        unsafe{ (self.0.count)(haystack, n1) }
+    }
+
+    #[inline]
+    fn count2(self, haystack: &[u8], n1: u8, n2: u8) -> usize {
+        unsafe{ (self.0.count2)(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn count3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+        unsafe{ (self.0.count3)(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn count_csv(self, haystack: &[u8], n1: u8) -> usize {
+        unsafe{ (self.0.count_csv)(haystack, n1) }
+    }
+
+    #[inline]
+    fn find_json_escape(self, haystack: &[u8]) -> Option<usize> {
+        unsafe{ (self.0.find_json_escape)(haystack) }
+    }
+}
+
+/// The avx2 kernels, called directly. Only built when avx2 is enabled at compile time
+/// (`-C target-cpu=native` or `-C target-feature=+avx2`), so every cpu running the binary supports it.
+#[cfg(target_feature = "avx2")]
+#[derive(Clone, Copy)]
+pub struct Avx2Kernels;
+
+#[cfg(target_feature = "avx2")]
+impl private::Sealed for Avx2Kernels {}
+
+#[cfg(target_feature = "avx2")]
+impl Kernels for Avx2Kernels {
+    #[inline]
+    fn backend(self) -> Backend {
+        Backend::Avx2
+    }
+
+    #[inline]
+    fn bufchr(self, haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+        unsafe{ avx::bufchr(haystack, n1) }
+    }
+
+    #[inline]
+    fn bufchr2(self, haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+        unsafe{ avx::bufchr2(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn bufchr3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+        unsafe{ avx::bufchr3(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn bufchr_csv(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+        unsafe{ avx::bufchr_csv(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn bufchr_utf8(self, haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+        unsafe{ avx::bufchr_utf8(haystack, n1, carry) }
+    }
+
+    #[inline]
+    fn bufchr_csv_utf8(self, haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+        unsafe{ avx::bufchr_csv_utf8(haystack, n1, n2, n3, carry) }
+    }
+
+    #[inline]
+    fn block_mask(self, haystack: &[u8], n1: u8) -> u64 {
+        unsafe{ avx::block_mask(haystack, n1) }
+    }
+
+    #[inline]
+    fn block_mask2(self, haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+        unsafe{ avx::block_mask2(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn block_mask3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+        unsafe{ avx::block_mask3(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn json_block(self, haystack: &[u8]) -> [u64; 4] {
+        unsafe{ avx::json_block(haystack) }
+    }
+
+    #[inline]
+    fn count(self, haystack: &[u8], n1: u8) -> usize {
+        unsafe{ avx::count(haystack, n1) }
+    }
+
+    #[inline]
+    fn count2(self, haystack: &[u8], n1: u8, n2: u8) -> usize {
+        unsafe{ avx::count2(haystack, n1, n2) }
+    }
+
+    #[inline]
+    fn count3(self, haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+        unsafe{ avx::count3(haystack, n1, n2, n3) }
+    }
+
+    #[inline]
+    fn count_csv(self, haystack: &[u8], n1: u8) -> usize {
+        unsafe{ avx::count_csv(haystack, n1) }
+    }
+
+    #[inline]
+    fn find_json_escape(self, haystack: &[u8]) -> Option<usize> {
+        unsafe{ avx::find_json_escape(haystack) }
+    }
+}
+
+/// The kernels used by `new` constructors and the count functions.
+/// It is `Avx2Kernels` when avx2 is enabled at compile time, otherwise the backend is selected at runtime.
+#[cfg(target_feature = "avx2")]
+pub type DefaultKernels = Avx2Kernels;
+/// The kernels used by `new` constructors and the count functions.
+/// It is `Avx2Kernels` when avx2 is enabled at compile time, otherwise the backend is selected at runtime.
+#[cfg(not(target_feature = "avx2"))]
+pub type DefaultKernels = DynamicKernels;
+
+#[cfg(target_feature = "avx2")]
+#[inline]
+pub(crate) fn default_kernels() -> DefaultKernels {
+    Avx2Kernels
+}
+
+#[cfg(not(target_feature = "avx2"))]
+#[inline]
+pub(crate) fn default_kernels() -> DefaultKernels {
+    DynamicKernels(dispatch())
+}
+
+/// Returns the kernels of backend, or None when they do not exist or the cpu does not support them.
+#[inline]
+pub(crate) fn dynamic_kernels(backend: Backend) -> Option<DynamicKernels> {
+    dispatch_for(backend).map(DynamicKernels)
+}
+
+/// Returns the number of times needle appears in haystack.
+#[inline]
+pub fn count(haystack: &[u8], needle0: u8) -> usize {
+    default_kernels().count(haystack, needle0)
+}
+
+/// Returns the number of bytes in haystack that are needle0 or needle1.
+#[inline]
+pub fn count2(haystack: &[u8], needle0: u8, needle1: u8) -> usize {
+    default_kernels().count2(haystack, needle0, needle1)
+}
+
+/// Returns the number of bytes in haystack that are needle0, needle1 or needle2.
+#[inline]
+pub fn count3(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8) -> usize {
+    default_kernels().count3(haystack, needle0, needle1, needle2)
+}
+
+/// Returns the number of positions `BufchrCSV` would return: needle0(the column delimiter), line feeds and double quotation marks.
+#[inline]
+pub fn count_csv(haystack: &[u8], needle0: u8) -> usize {
+    default_kernels().count_csv(haystack, needle0)
+}
+
+/// Like `count`, but uses the kernels of backend. None is returned when backend is not available.
+#[inline]
+pub fn count_with_backend(haystack: &[u8], needle0: u8, backend: Backend) -> Option<usize> {
+    Some(dynamic_kernels(backend)?.count(haystack, needle0))
+}
+
+/// Like `count2`, but uses the kernels of backend. None is returned when backend is not available.
+#[inline]
+pub fn count2_with_backend(haystack: &[u8], needle0: u8, needle1: u8, backend: Backend) -> Option<usize> {
+    Some(dynamic_kernels(backend)?.count2(haystack, needle0, needle1))
+}
+
+/// Like `count3`, but uses the kernels of backend. None is returned when backend is not available.
+#[inline]
+pub fn count3_with_backend(haystack: &[u8], needle0: u8, needle1: u8, needle2: u8, backend: Backend) -> Option<usize> {
+    Some(dynamic_kernels(backend)?.count3(haystack, needle0, needle1, needle2))
+}
+
+/// Like `count_csv`, but uses the kernels of backend. None is returned when backend is not available.
+#[inline]
+pub fn count_csv_with_backend(haystack: &[u8], needle0: u8, backend: Backend) -> Option<usize> {
+    Some(dynamic_kernels(backend)?.count_csv(haystack, needle0))
+}
+
+/// Returns the position of the first byte of haystack that has to be escaped in a JSON string:
+/// a double quotation mark, a backslash or a control character(below 0x20).
+///
+/// ```
+/// assert_eq!(bufchr::find_json_escape(b"plain text"), None);
+/// assert_eq!(bufchr::find_json_escape(b"say \"hi\""), Some(4));
+/// assert_eq!(bufchr::find_json_escape(b"tab\there"), Some(3));
+/// ```
+#[inline]
+pub fn find_json_escape(haystack: &[u8]) -> Option<usize> {
+    default_kernels().find_json_escape(haystack)
 }
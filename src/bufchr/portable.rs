@@ -0,0 +1,155 @@
+use core::simd::cmp::{SimdOrd, SimdPartialEq, SimdPartialOrd};
+use core::simd::num::{SimdInt, SimdUint};
+use core::simd::Simd;
+use crate::bufchr::vector::{self, Vector};
+use crate::bufchr::Utf8Scan;
+
+// One vector is one 64 byte block, so `to_bitmask()` is the cache mask as is.
+type U8x64 = Simd<u8, 64>;
+
+// core::simd runs on any cpu, so none of these methods needs a cpu feature.
+impl Vector for U8x64 {
+    const BYTES: usize = 64;
+
+    #[inline(always)]
+    unsafe fn splat(byte: u8) -> U8x64 {
+        U8x64::splat(byte)
+    }
+
+    #[inline(always)]
+    unsafe fn zero() -> U8x64 {
+        U8x64::splat(0)
+    }
+
+    #[inline(always)]
+    unsafe fn load(ptr: *const u8) -> U8x64 {
+        U8x64::from_array((ptr as *const [u8; 64]).read_unaligned())
+    }
+
+    #[inline(always)]
+    unsafe fn cmpeq(self, other: U8x64) -> U8x64 {
+        self.simd_eq(other).to_simd().cast()
+    }
+
+    #[inline(always)]
+    unsafe fn or(self, other: U8x64) -> U8x64 {
+        self | other
+    }
+
+    #[inline(always)]
+    unsafe fn and(self, other: U8x64) -> U8x64 {
+        self & other
+    }
+
+    #[inline(always)]
+    unsafe fn xor(self, other: U8x64) -> U8x64 {
+        self ^ other
+    }
+
+    #[inline(always)]
+    unsafe fn sub_bytes(self, other: U8x64) -> U8x64 {
+        self - other
+    }
+
+    #[inline(always)]
+    unsafe fn sub_sat(self, other: U8x64) -> U8x64 {
+        self.saturating_sub(other)
+    }
+
+    #[inline(always)]
+    unsafe fn min_bytes(self, other: U8x64) -> U8x64 {
+        self.simd_min(other)
+    }
+
+    #[inline(always)]
+    unsafe fn high_nibbles(self) -> U8x64 {
+        self >> U8x64::splat(4)
+    }
+
+    #[inline(always)]
+    unsafe fn lookup16(self, table: &[u8; 16]) -> U8x64 {
+        let mut table64 = [0; 64];
+        table64[..16].copy_from_slice(table);
+        U8x64::from_array(table64).swizzle_dyn(self)
+    }
+
+    #[inline(always)]
+    unsafe fn movemask(self) -> u64 {
+        self.simd_ge(U8x64::splat(0x80)).to_bitmask()
+    }
+
+    #[inline(always)]
+    unsafe fn sum_bytes(self) -> usize {
+        // 64 * 255 fits in u16.
+        self.cast::<u16>().reduce_sum() as usize
+    }
+}
+
+pub fn bufchr(haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = unsafe{ vector::find::<U8x64, 1, 1>(haystack, [n1]) };
+    (position, cache)
+}
+
+pub fn bufchr2(haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = unsafe{ vector::find::<U8x64, 2, 1>(haystack, [n1, n2]) };
+    (position, cache)
+}
+
+pub fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = unsafe{ vector::find::<U8x64, 3, 1>(haystack, [n1, n2, n3]) };
+    (position, cache)
+}
+
+pub fn bufchr_csv(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+    let (position, [cache1, cache2]) = unsafe{ vector::find::<U8x64, 3, 2>(haystack, [n1, n2, n3]) };
+    (position, cache1, cache2)
+}
+
+pub fn bufchr_utf8(haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+    let (position, [cache], scan) = unsafe{ vector::find_utf8::<U8x64, 1, 1>(haystack, [n1], carry) };
+    (position, cache, scan)
+}
+
+pub fn bufchr_csv_utf8(haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+    let (position, [cache1, cache2], scan) = unsafe{ vector::find_utf8::<U8x64, 3, 2>(haystack, [n1, n2, n3], carry) };
+    (position, cache1, cache2, scan)
+}
+
+/// Returns the mask of needles in the first 64 bytes of haystack.
+pub fn block_mask(haystack: &[u8], n1: u8) -> u64 {
+    unsafe{ vector::block_masks::<U8x64, 1>(haystack, [n1])[0] }
+}
+
+pub fn block_mask2(haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+    unsafe{ vector::block_masks::<U8x64, 2>(haystack, [n1, n2]) }
+}
+
+pub fn block_mask3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+    unsafe{ vector::block_masks::<U8x64, 3>(haystack, [n1, n2, n3]) }
+}
+
+/// Returns [quote, backslash, op, whitespace] masks of the first 64 bytes of haystack.
+/// op is one of `{}[]:,` and whitespace is one of space, `\t`, `\n`, `\r`.
+pub fn json_block(haystack: &[u8]) -> [u64; 4] {
+    unsafe{ vector::json_block::<U8x64>(haystack) }
+}
+
+pub fn count(haystack: &[u8], n1: u8) -> usize {
+    unsafe{ vector::count::<U8x64, 1>(haystack, [n1]) }
+}
+
+pub fn count2(haystack: &[u8], n1: u8, n2: u8) -> usize {
+    unsafe{ vector::count::<U8x64, 2>(haystack, [n1, n2]) }
+}
+
+pub fn count3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+    unsafe{ vector::count::<U8x64, 3>(haystack, [n1, n2, n3]) }
+}
+
+pub fn count_csv(haystack: &[u8], n1: u8) -> usize {
+    unsafe{ vector::count::<U8x64, 3>(haystack, [n1, b'\n', b'"']) }
+}
+
+pub fn find_json_escape(haystack: &[u8]) -> Option<usize> {
+    unsafe{ vector::find_json_escape::<U8x64>(haystack) }
+}
@@ -1,261 +1,169 @@
-use core::{arch::x86_64::*, cmp, mem::size_of};
-use crate::bufchr::fallback;
+use core::{arch::x86_64::*, mem::size_of};
+use crate::bufchr::vector::{self, Vector};
+use crate::bufchr::Utf8Scan;
 
 const VECTOR_SIZE: usize = size_of::<__m128i>();
-const LOOP_COUNT: usize = 4;
-const CACHE_MASK_SIZE: usize = 64;
-const BATCH_BYTE_SIZE: usize = VECTOR_SIZE * LOOP_COUNT;
-const BATCH_BYTE_SIZE2: usize = VECTOR_SIZE * LOOP_COUNT * 2;
 
+impl Vector for __m128i {
+    const BYTES: usize = VECTOR_SIZE;
 
-const LINE_FEED_M128I:__m128i = unsafe {
-    std::mem::transmute::<[u32;4], __m128i>(
-        [0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A, 0x0A0A0A0A])
-};
-
-const DOUBLE_QUOTATION_M128I:__m128i = unsafe {
-    std::mem::transmute::<[u32;4], __m128i>(
-        [0x22222222, 0x22222222, 0x22222222, 0x22222222])
-};
-
-pub fn get_vector_size() -> usize {
-    VECTOR_SIZE
-}
-
-pub fn get_batch_byte_size() -> usize {
-    return VECTOR_SIZE * 4;
-}
-
-pub unsafe fn bufchr(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr(haystack, n1, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn splat(byte: u8) -> __m128i {
+        _mm_set1_epi8(byte as i8)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = haystack.as_ptr();
-    let vn1 = _mm_set1_epi8(n1 as i8);
 
-    while ptr < vector_end_ptr{
-        // https://stackoverflow.com/a/15964428/6652082
-        // if memory alignment work, use _mm_load_si128
-        let chunk1 = _mm_loadu_si128(ptr as *const __m128i);
-        let chunk2 = _mm_loadu_si128(ptr.add(VECTOR_SIZE) as *const __m128i);
-        let chunk3 = _mm_loadu_si128(ptr.add(VECTOR_SIZE*2) as *const __m128i);
-        let chunk4 = _mm_loadu_si128(ptr.add(VECTOR_SIZE*3) as *const __m128i);
-        let mask1 = _mm_movemask_epi8(_mm_cmpeq_epi8(vn1, chunk1));
-        let mask2 = _mm_movemask_epi8(_mm_cmpeq_epi8(vn1, chunk2));
-        let mask3 = _mm_movemask_epi8(_mm_cmpeq_epi8(vn1, chunk3));
-        let mask4 = _mm_movemask_epi8(_mm_cmpeq_epi8(vn1, chunk4));
-        if (mask1 | mask2 | mask3 | mask4) != 0 {
-            let umask = to_u64(mask1, mask2, mask3, mask4);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+    #[inline(always)]
+    unsafe fn zero() -> __m128i {
+        _mm_setzero_si128()
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        vector_end_ptr, haystack_len % VECTOR_SIZE);
-    fallback::bufchr(rest_haystack, n1, vector_end_ptr)
-}
-
-#[target_feature(enable = "sse2")]
-pub unsafe fn bufchr2(haystack: &[u8], n1: u8, n2: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr2(haystack, n1, n2, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn load(ptr: *const u8) -> __m128i {
+        _mm_loadu_si128(ptr as *const __m128i)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let align_end_ptr = start_ptr.add((haystack_len / VECTOR_SIZE) * VECTOR_SIZE);
-    let vn1 = _mm_set1_epi8(n1 as i8);
-    let vn2 = _mm_set1_epi8(n2 as i8);
 
-    while ptr < vector_end_ptr{
-        // https://stackoverflow.com/a/15964428/6652082
-        // if memory alignment work, use _mm_load_si128
-        let chunk = _mm_loadu_si128(ptr as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let mask1 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2);
+    #[inline(always)]
+    unsafe fn cmpeq(self, other: __m128i) -> __m128i {
+        _mm_cmpeq_epi8(self, other)
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let mask2 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2);
+    #[inline(always)]
+    unsafe fn or(self, other: __m128i) -> __m128i {
+        _mm_or_si128(self, other)
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 2) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let mask3 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2);
+    #[inline(always)]
+    unsafe fn and(self, other: __m128i) -> __m128i {
+        _mm_and_si128(self, other)
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 3) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let mask4 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2);
-        
-        if (mask1 | mask2 | mask3 | mask4) != 0 {
-            let umask = to_u64(mask1, mask2, mask3, mask4);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+    #[inline(always)]
+    unsafe fn xor(self, other: __m128i) -> __m128i {
+        _mm_xor_si128(self, other)
     }
 
-    let rest_haystack = std::slice::from_raw_parts(
-        align_end_ptr, haystack_len % VECTOR_SIZE);
-    fallback::bufchr2(rest_haystack, n1, n2, vector_end_ptr)
-}
+    #[inline(always)]
+    unsafe fn sub_bytes(self, other: __m128i) -> __m128i {
+        _mm_sub_epi8(self, other)
+    }
 
-#[target_feature(enable = "sse2")]
-pub unsafe fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE {
-        return fallback::bufchr3(haystack, n1, n2, n3, vector_end_ptr);
+    #[inline(always)]
+    unsafe fn sub_sat(self, other: __m128i) -> __m128i {
+        _mm_subs_epu8(self, other)
     }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let vn1 = _mm_set1_epi8(n1 as i8);
-    let vn2 = _mm_set1_epi8(n2 as i8);
-    let vn3 = _mm_set1_epi8(n3 as i8);
 
-    while ptr < vector_end_ptr{
-        // https://stackoverflow.com/a/15964428/6652082
-        // if memory alignment work, use _mm_load_si128
-        let chunk = _mm_loadu_si128(ptr as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask1 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+    #[inline(always)]
+    unsafe fn min_bytes(self, other: __m128i) -> __m128i {
+        _mm_min_epu8(self, other)
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask2 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+    #[inline(always)]
+    unsafe fn high_nibbles(self) -> __m128i {
+        _mm_and_si128(_mm_srli_epi16(self, 4), _mm_set1_epi8(0x0F))
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 2) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask3 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+    // sse2 has no byte shuffle, so the table is looked up through memory.
+    // Only blocks that are not ASCII are looked up.
+    #[inline(always)]
+    unsafe fn lookup16(self, table: &[u8; 16]) -> __m128i {
+        let indices = core::mem::transmute::<__m128i, [u8; 16]>(self);
+        core::mem::transmute::<[u8; 16], __m128i>(indices.map(|index| table[index as usize & 0x0F]))
+    }
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 3) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask4 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+    #[inline(always)]
+    unsafe fn movemask(self) -> u64 {
+        _mm_movemask_epi8(self) as u32 as u64
+    }
 
-        if (mask1 | mask2 | mask3 | mask4) != 0 {
-            let umask = to_u64(mask1, mask2, mask3, mask4);
-            let bit_pos = umask.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask & (umask - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache);
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE);
+    #[inline(always)]
+    unsafe fn sum_bytes(self) -> usize {
+        let sum = _mm_sad_epu8(self, _mm_setzero_si128());
+        let lanes = core::mem::transmute::<__m128i, [u64; 2]>(sum);
+        (lanes[0] + lanes[1]) as usize
     }
-    return fallback::bufchr3(haystack, n1, n2, n3, vector_end_ptr);
 }
 
 #[target_feature(enable = "sse2")]
-pub unsafe fn bufchr_csv(haystack: &[u8], n1: u8, vector_end_ptr: *const u8) -> (Option<usize>, u64, u64) {
-    let haystack_len = haystack.len();
-    if haystack_len < BATCH_BYTE_SIZE2 {
-        return fallback::bufchr_csv(haystack, n1, vector_end_ptr);
-    }
-    let start_ptr = haystack.as_ptr();
-    let mut ptr = start_ptr;
-    let vn1 = _mm_set1_epi8(n1 as i8);
-    let &vn2 = &LINE_FEED_M128I;
-    let &vn3 = &DOUBLE_QUOTATION_M128I;
+pub unsafe fn bufchr(haystack: &[u8], n1: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m128i, 1, 1>(haystack, [n1]);
+    (position, cache)
+}
 
-    while ptr < vector_end_ptr{
-        // https://stackoverflow.com/a/15964428/6652082
-        // if memory alignment work, use _mm_load_si128
-        let chunk = _mm_loadu_si128(ptr as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask1 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn bufchr2(haystack: &[u8], n1: u8, n2: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m128i, 2, 1>(haystack, [n1, n2]);
+    (position, cache)
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask2 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn bufchr3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64) {
+    let (position, [cache]) = vector::find::<__m128i, 3, 1>(haystack, [n1, n2, n3]);
+    (position, cache)
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 2) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask3 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+/// Searches 128 bytes per batch and returns the masks of both 64 byte blocks.
+#[target_feature(enable = "sse2")]
+pub unsafe fn bufchr_csv(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> (Option<usize>, u64, u64) {
+    let (position, [cache1, cache2]) = vector::find::<__m128i, 3, 2>(haystack, [n1, n2, n3]);
+    (position, cache1, cache2)
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 3) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask4 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn bufchr_utf8(haystack: &[u8], n1: u8, carry: [u8; 3]) -> (Option<usize>, u64, Utf8Scan) {
+    let (position, [cache], scan) = vector::find_utf8::<__m128i, 1, 1>(haystack, [n1], carry);
+    (position, cache, scan)
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 4) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask5 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn bufchr_csv_utf8(haystack: &[u8], n1: u8, n2: u8, n3: u8, carry: [u8; 3]) -> (Option<usize>, u64, u64, Utf8Scan) {
+    let (position, [cache1, cache2], scan) = vector::find_utf8::<__m128i, 3, 2>(haystack, [n1, n2, n3], carry);
+    (position, cache1, cache2, scan)
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 5) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask6 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+/// Returns the mask of needles in the first 64 bytes of haystack.
+#[target_feature(enable = "sse2")]
+pub unsafe fn block_mask(haystack: &[u8], n1: u8) -> u64 {
+    vector::block_masks::<__m128i, 1>(haystack, [n1])[0]
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 6) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask7 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn block_mask2(haystack: &[u8], n1: u8, n2: u8) -> [u64; 2] {
+    vector::block_masks::<__m128i, 2>(haystack, [n1, n2])
+}
 
-        let chunk = _mm_loadu_si128(ptr.add(VECTOR_SIZE * 7) as *const __m128i);
-        let eq1 = _mm_cmpeq_epi8(vn1, chunk);
-        let eq2 = _mm_cmpeq_epi8(vn2, chunk);
-        let eq3 = _mm_cmpeq_epi8(vn3, chunk);
-        let mask8 = _mm_movemask_epi8(eq1) | _mm_movemask_epi8(eq2) | _mm_movemask_epi8(eq3);
+#[target_feature(enable = "sse2")]
+pub unsafe fn block_mask3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> [u64; 3] {
+    vector::block_masks::<__m128i, 3>(haystack, [n1, n2, n3])
+}
 
-        if (mask1 | mask2 | mask3 | mask4 ) != 0 {
-            let umask1 = to_u64(mask1, mask2, mask3, mask4);
-            let umask2 = to_u64(mask5, mask6, mask7, mask8);
-            let bit_pos = umask1.trailing_zeros() as usize;
-            // Reset lowest set bit	
-            let cache = umask1 & (umask1 - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos), cache, umask2);
-        }
-        else if( mask5 | mask6 | mask7 | mask8) != 0 {
-            let umask2 = to_u64(mask5, mask6, mask7, mask8);
-            let bit_pos = umask2.trailing_zeros() as usize ;
-            // Reset lowest set bit	
-            let cache = umask2 & (umask2 - 1);   
-            return (Some(sub(ptr, start_ptr) + bit_pos + CACHE_MASK_SIZE), 0, cache);
-            
-        }
-        ptr = ptr.add(BATCH_BYTE_SIZE2);
-    }
+/// Returns [quote, backslash, op, whitespace] masks of the first 64 bytes of haystack.
+/// op is one of `{}[]:,` and whitespace is one of space, `\t`, `\n`, `\r`.
+#[target_feature(enable = "sse2")]
+pub unsafe fn json_block(haystack: &[u8]) -> [u64; 4] {
+    vector::json_block::<__m128i>(haystack)
+}
 
-    return fallback::bufchr_csv(haystack, n1, vector_end_ptr);
+#[target_feature(enable = "sse2")]
+pub unsafe fn count(haystack: &[u8], n1: u8) -> usize {
+    vector::count::<__m128i, 1>(haystack, [n1])
 }
 
+#[target_feature(enable = "sse2")]
+pub unsafe fn count2(haystack: &[u8], n1: u8, n2: u8) -> usize {
+    vector::count::<__m128i, 2>(haystack, [n1, n2])
+}
 
-#[inline]
-fn sub(a: *const u8, b: *const u8) -> usize {
-    debug_assert!(a >= b);
-    (a as usize) - (b as usize)
+#[target_feature(enable = "sse2")]
+pub unsafe fn count3(haystack: &[u8], n1: u8, n2: u8, n3: u8) -> usize {
+    vector::count::<__m128i, 3>(haystack, [n1, n2, n3])
 }
 
+#[target_feature(enable = "sse2")]
+pub unsafe fn count_csv(haystack: &[u8], n1: u8) -> usize {
+    vector::count::<__m128i, 3>(haystack, [n1, b'\n', b'"'])
+}
 
-#[inline]
-fn to_u64(i1: i32, i2: i32, i3: i32, i4: i32) -> u64 {
-    (i4 as u64) << 48 | (i3 as u64) <<32 | (i2  as u64) << 16 | (i1 as u64)
+#[target_feature(enable = "sse2")]
+pub unsafe fn find_json_escape(haystack: &[u8]) -> Option<usize> {
+    vector::find_json_escape::<__m128i>(haystack)
 }
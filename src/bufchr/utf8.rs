@@ -0,0 +1,107 @@
+/*!
+UTF-8 validation of the bytes an iterator searches, for its `validate_utf8` mode.
+
+The `_utf8` kernels check every batch they search, so the haystack is read once for both.
+Only the end of the haystack after the last batch and the bytes around an error are checked with `core::str`.
+*/
+
+use crate::bufchr::Utf8Scan;
+
+/// What is known of the UTF-8 of a haystack, updated with the scan of every kernel call.
+#[derive(Clone, Copy)]
+pub(crate) struct Utf8Validator<'a> {
+    haystack: &'a [u8],
+    enabled: bool,
+    // Every byte before checked is valid UTF-8, but the sequence at checked may be unfinished.
+    checked: usize,
+    // The whole haystack is checked, up to the first error if there is one.
+    done: bool,
+    error: Option<usize>,
+}
+
+impl<'a> Utf8Validator<'a> {
+    #[inline]
+    pub(crate) fn new(haystack: &'a [u8]) -> Utf8Validator<'a> {
+        Utf8Validator { haystack, enabled: false, checked: 0, done: false, error: None }
+    }
+
+    #[inline]
+    pub(crate) fn enable(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// True while the `_utf8` kernels have to be called.
+    #[inline]
+    pub(crate) fn is_active(&self) -> bool {
+        self.enabled && !self.done
+    }
+
+    /// The 3 bytes before offset, 0 where there are none.
+    #[inline]
+    pub(crate) fn carry(&self, offset: usize) -> [u8; 3] {
+        let mut carry = [0; 3];
+        for (i, byte) in carry.iter_mut().enumerate() {
+            if offset + i >= 3 {
+                *byte = self.haystack[offset + i - 3];
+            }
+        }
+        carry
+    }
+
+    /// Takes the scan of a kernel that searched the haystack from offset and returned position, relative to offset.
+    /// The iterator moves past everything the kernel checked, and when position is not in it, past the end.
+    #[inline]
+    pub(crate) fn update(&mut self, offset: usize, scan: Utf8Scan, position: Option<usize>) {
+        debug_assert!(offset <= self.checked);
+        match scan {
+            Utf8Scan::Valid(len) => {
+                self.checked = self.checked.max(offset + len);
+                let moves_past = match position {
+                    Some(pos) => offset + pos >= self.checked,
+                    None => true,
+                };
+                if moves_past {
+                    self.finish(self.checked);
+                }
+            }
+            Utf8Scan::Invalid(pos) => self.finish(offset + pos),
+        }
+    }
+
+    /// Checks the rest of the haystack from a character boundary at most 4 bytes before from,
+    /// which finds the first error when the bytes before from are valid.
+    #[cold]
+    fn finish(&mut self, from: usize) {
+        let lowest = from.saturating_sub(4);
+        let start = (lowest..from).rev().find(|&i| self.haystack[i] & 0xC0 != 0x80).unwrap_or(lowest);
+        self.error = core::str::from_utf8(&self.haystack[start..]).err().map(|err| start + err.valid_up_to());
+        self.checked = self.haystack.len();
+        self.done = true;
+    }
+
+    /// The offset of the first byte that is not valid UTF-8, once it is checked.
+    #[inline]
+    pub(crate) fn error(&self) -> Option<usize> {
+        self.error
+    }
+
+    /// haystack[start..end] as a str, when the bytes up to end are checked and valid,
+    /// and start and end are character boundaries.
+    #[inline]
+    pub(crate) fn get_str(&self, start: usize, end: usize) -> Option<&'a str> {
+        let is_boundary = |i: usize| !matches!(self.haystack.get(i), Some(&b) if b & 0xC0 == 0x80);
+        let valid = match (self.done, self.error) {
+            (true, Some(error)) => end == error || (end < error && is_boundary(end)),
+            (true, None) => end <= self.haystack.len() && is_boundary(end),
+            // The sequence at checked may be unfinished, so the byte at end must be checked too.
+            (false, _) => end < self.checked && is_boundary(end),
+        };
+        if !self.enabled || !valid || start > end || !(start == end || is_boundary(start)) {
+            return None;
+        }
+        let bytes = &self.haystack[start..end];
+        debug_assert!(core::str::from_utf8(bytes).is_ok());
+        // Every byte before end is valid UTF-8, and a slice of it between two boundaries is too.
+        Some(unsafe{ core::str::from_utf8_unchecked(bytes) })
+    }
+}
@@ -0,0 +1,376 @@
+/*!
+Kernels written once over a `Vector` of bytes.
+
+A backend implements the primitive operations of `Vector` for its register type and
+gets every search mode from the generic kernels below, for any needle count `N`
+and any number of 64 byte blocks per batch `BLOCKS`.
+
+Every function here is `#[inline(always)]`, so it is compiled inside the `#[target_feature]`
+wrapper of the backend that calls it and the intrinsics are inlined. They follow the kernel
+contract in `mod.rs`: only bytes inside haystack are read.
+*/
+
+use crate::bufchr::{fallback, Utf8Scan};
+
+/// Bytes of one cache mask. Positions are reported for 64 byte blocks whatever the vector size is.
+pub const BLOCK_BYTE_SIZE: usize = 64;
+
+// Each byte of an accumulator counts up to 255 matches before it overflows.
+const MAX_ACC_LOOP: usize = 255;
+
+/// Primitive operations of a simd register of `BYTES` bytes.
+/// The methods are unsafe because the cpu must support the instructions.
+pub trait Vector: Copy {
+    /// Bytes in the register. It must divide 64.
+    const BYTES: usize;
+
+    /// Every byte is byte.
+    unsafe fn splat(byte: u8) -> Self;
+    /// Every byte is 0.
+    unsafe fn zero() -> Self;
+    /// Unaligned load of `BYTES` bytes from ptr.
+    unsafe fn load(ptr: *const u8) -> Self;
+    /// 0xFF for equal bytes, 0 otherwise.
+    unsafe fn cmpeq(self, other: Self) -> Self;
+    unsafe fn or(self, other: Self) -> Self;
+    unsafe fn and(self, other: Self) -> Self;
+    unsafe fn xor(self, other: Self) -> Self;
+    /// Wrapping subtraction of each byte.
+    unsafe fn sub_bytes(self, other: Self) -> Self;
+    /// Saturating subtraction of each unsigned byte.
+    unsafe fn sub_sat(self, other: Self) -> Self;
+    /// Unsigned minimum of each byte.
+    unsafe fn min_bytes(self, other: Self) -> Self;
+    /// The high nibble of each byte, shifted down to 0..16.
+    unsafe fn high_nibbles(self) -> Self;
+    /// Byte i is `table[self[i]]`. Every byte of self must be below 16.
+    unsafe fn lookup16(self, table: &[u8; 16]) -> Self;
+    /// Bit i is the top bit of byte i.
+    unsafe fn movemask(self) -> u64;
+    /// Sum of all bytes as unsigned values.
+    unsafe fn sum_bytes(self) -> usize;
+}
+
+/// Returns the position of the first needle and the masks of the batch of `BLOCKS * 64` bytes it is in.
+/// The bit of the returned position is cleared and the masks before its block are 0.
+/// A needle found in the tail shorter than a batch is returned with empty masks.
+#[inline(always)]
+pub unsafe fn find<V: Vector, const N: usize, const BLOCKS: usize>(
+    haystack: &[u8], needles: [u8; N]) -> (Option<usize>, [u64; BLOCKS]) {
+    let batch_byte_size = BLOCK_BYTE_SIZE * BLOCKS;
+    let haystack_len = haystack.len();
+    if haystack_len < batch_byte_size {
+        return (fallback::find_any(haystack, &needles), [0; BLOCKS]);
+    }
+    let start_ptr = haystack.as_ptr();
+    let mut ptr = start_ptr;
+    let vector_end_ptr = start_ptr.add((haystack_len / batch_byte_size) * batch_byte_size);
+    let vn = splat_all::<V, N>(needles);
+
+    while ptr < vector_end_ptr {
+        debug_assert!(distance(ptr, start_ptr) + batch_byte_size <= haystack_len);
+        let mut masks = [0; BLOCKS];
+        for (block, mask) in masks.iter_mut().enumerate() {
+            *mask = any_mask(ptr.add(block * BLOCK_BYTE_SIZE), &vn);
+        }
+        if let Some(block) = masks.iter().position(|&mask| mask != 0) {
+            let bit_pos = masks[block].trailing_zeros() as usize;
+            // Reset lowest set bit
+            masks[block] &= masks[block] - 1;
+            return (Some(distance(ptr, start_ptr) + block * BLOCK_BYTE_SIZE + bit_pos), masks);
+        }
+        ptr = ptr.add(batch_byte_size);
+    }
+
+    let rest_haystack = &haystack[distance(vector_end_ptr, start_ptr)..];
+    match fallback::find_any(rest_haystack, &needles) {
+        Some(pos) => (Some(distance(ptr, start_ptr) + pos), [0; BLOCKS]),
+        None => (None, [0; BLOCKS]),
+    }
+}
+
+/// Like `find`, and also checks that the batches it searches are valid UTF-8 after carry,
+/// the 3 bytes before haystack(0 where there are none).
+/// The batches up to the one of the returned position are checked, or all of them when there is none.
+/// The tail shorter than a batch is not checked, and a sequence may be unfinished at the end of what was.
+/// After the first invalid block, the search goes on without checking.
+#[inline(always)]
+pub unsafe fn find_utf8<V: Vector, const N: usize, const BLOCKS: usize>(
+    haystack: &[u8], needles: [u8; N], carry: [u8; 3]) -> (Option<usize>, [u64; BLOCKS], Utf8Scan) {
+    let batch_byte_size = BLOCK_BYTE_SIZE * BLOCKS;
+    let haystack_len = haystack.len();
+    if haystack_len < batch_byte_size {
+        return (fallback::find_any(haystack, &needles), [0; BLOCKS], Utf8Scan::Valid(0));
+    }
+    let start_ptr = haystack.as_ptr();
+    let mut ptr = start_ptr;
+    let vector_end_ptr = start_ptr.add((haystack_len / batch_byte_size) * batch_byte_size);
+    let vn = splat_all::<V, N>(needles);
+    let mut invalid = None;
+
+    while ptr < vector_end_ptr {
+        debug_assert!(distance(ptr, start_ptr) + batch_byte_size <= haystack_len);
+        let mut masks = [0; BLOCKS];
+        for (block, mask) in masks.iter_mut().enumerate() {
+            let block_ptr = ptr.add(block * BLOCK_BYTE_SIZE);
+            *mask = any_mask(block_ptr, &vn);
+            if invalid.is_none() {
+                let prev = if block_ptr == start_ptr {
+                    carry
+                }
+                else {
+                    [*block_ptr.sub(3), *block_ptr.sub(2), *block_ptr.sub(1)]
+                };
+                if !utf8_block::<V>(block_ptr, prev) {
+                    invalid = Some(distance(block_ptr, start_ptr));
+                }
+            }
+        }
+        if let Some(block) = masks.iter().position(|&mask| mask != 0) {
+            let bit_pos = masks[block].trailing_zeros() as usize;
+            // Reset lowest set bit
+            masks[block] &= masks[block] - 1;
+            let position = distance(ptr, start_ptr) + block * BLOCK_BYTE_SIZE + bit_pos;
+            return (Some(position), masks, utf8_scan(invalid, distance(ptr, start_ptr) + batch_byte_size));
+        }
+        ptr = ptr.add(batch_byte_size);
+    }
+
+    let rest = distance(vector_end_ptr, start_ptr);
+    let position = fallback::find_any(&haystack[rest..], &needles).map(|pos| rest + pos);
+    (position, [0; BLOCKS], utf8_scan(invalid, rest))
+}
+
+#[inline(always)]
+fn utf8_scan(invalid: Option<usize>, checked: usize) -> Utf8Scan {
+    match invalid {
+        Some(block) => Utf8Scan::Invalid(block),
+        None => Utf8Scan::Valid(checked),
+    }
+}
+
+/// Returns the mask of each needle in the first 64 bytes of haystack. haystack must be at least 64 bytes.
+#[inline(always)]
+pub unsafe fn block_masks<V: Vector, const N: usize>(haystack: &[u8], needles: [u8; N]) -> [u64; N] {
+    debug_assert!(haystack.len() >= BLOCK_BYTE_SIZE);
+    let ptr = haystack.as_ptr();
+    let vn = splat_all::<V, N>(needles);
+    let mut masks = [0; N];
+    for lane in 0..BLOCK_BYTE_SIZE / V::BYTES {
+        let chunk = V::load(ptr.add(lane * V::BYTES));
+        for (mask, &v) in masks.iter_mut().zip(vn.iter()) {
+            *mask |= chunk.cmpeq(v).movemask() << (lane * V::BYTES);
+        }
+    }
+    masks
+}
+
+/// Returns the number of bytes of haystack that are one of needles.
+#[inline(always)]
+pub unsafe fn count<V: Vector, const N: usize>(haystack: &[u8], needles: [u8; N]) -> usize {
+    let haystack_len = haystack.len();
+    let start_ptr = haystack.as_ptr();
+    let mut ptr = start_ptr;
+    let vector_end_ptr = start_ptr.add((haystack_len / V::BYTES) * V::BYTES);
+    let vn = splat_all::<V, N>(needles);
+    let mut count = 0;
+
+    while ptr < vector_end_ptr {
+        let mut acc = V::zero();
+        let mut loop_count = 0;
+        while ptr < vector_end_ptr && loop_count < MAX_ACC_LOOP {
+            debug_assert!(distance(ptr, start_ptr) + V::BYTES <= haystack_len);
+            // A matching byte is 0xFF(-1), so subtracting it adds one to the counter.
+            acc = acc.sub_bytes(any_eq(V::load(ptr), &vn));
+            ptr = ptr.add(V::BYTES);
+            loop_count += 1;
+        }
+        count += acc.sum_bytes();
+    }
+
+    let rest_haystack = &haystack[distance(vector_end_ptr, start_ptr)..];
+    count + fallback::count_any(rest_haystack, &needles)
+}
+
+/// Returns the position of the first byte that must be escaped in a JSON string:
+/// a quote, a backslash or a control character(below 0x20).
+#[inline(always)]
+pub unsafe fn find_json_escape<V: Vector>(haystack: &[u8]) -> Option<usize> {
+    let haystack_len = haystack.len();
+    let start_ptr = haystack.as_ptr();
+    let mut ptr = start_ptr;
+    let vector_end_ptr = start_ptr.add((haystack_len / V::BYTES) * V::BYTES);
+    let quote = V::splat(b'"');
+    let backslash = V::splat(b'\\');
+    let max_control = V::splat(0x1F);
+
+    while ptr < vector_end_ptr {
+        debug_assert!(distance(ptr, start_ptr) + V::BYTES <= haystack_len);
+        let chunk = V::load(ptr);
+        // A byte is at most 0x1F when it is the minimum of itself and 0x1F.
+        let control = chunk.min_bytes(max_control).cmpeq(chunk);
+        let mask = chunk.cmpeq(quote).or(chunk.cmpeq(backslash)).or(control).movemask();
+        if mask != 0 {
+            return Some(distance(ptr, start_ptr) + mask.trailing_zeros() as usize);
+        }
+        ptr = ptr.add(V::BYTES);
+    }
+
+    let rest = distance(vector_end_ptr, start_ptr);
+    fallback::find_json_escape(&haystack[rest..]).map(|pos| rest + pos)
+}
+
+/// Returns [quote, backslash, op, whitespace] masks of the first 64 bytes of haystack.
+/// op is one of `{}[]:,` and whitespace is one of space, `\t`, `\n`, `\r`. haystack must be at least 64 bytes.
+#[inline(always)]
+pub unsafe fn json_block<V: Vector>(haystack: &[u8]) -> [u64; 4] {
+    debug_assert!(haystack.len() >= BLOCK_BYTE_SIZE);
+    let ptr = haystack.as_ptr();
+    let mut masks = [0; 4];
+    for lane in 0..BLOCK_BYTE_SIZE / V::BYTES {
+        let chunk = V::load(ptr.add(lane * V::BYTES));
+        // Comparing every byte of a class is as fast as a lookup with avx2, and sse2 has no byte shuffle.
+        let eq = |b: u8| chunk.cmpeq(V::splat(b));
+        let op = eq(b'{').or(eq(b'}')).or(eq(b'[').or(eq(b']'))).or(eq(b':').or(eq(b',')));
+        let whitespace = eq(b' ').or(eq(b'\t')).or(eq(b'\n').or(eq(b'\r')));
+        let shift = lane * V::BYTES;
+        masks[0] |= eq(b'"').movemask() << shift;
+        masks[1] |= eq(b'\\').movemask() << shift;
+        masks[2] |= op.movemask() << shift;
+        masks[3] |= whitespace.movemask() << shift;
+    }
+    masks
+}
+
+// Error classes of the lookup UTF-8 check of Keiser and Lemire,
+// "Validating UTF-8 In Less Than One Instruction Per Byte" (2021).
+// Every byte and the byte before it are looked up by three nibbles, and a class set in all three is an error.
+// Only a 3rd or 4th byte of a sequence may be the second of two continuations, which is checked apart.
+// A lead followed by ASCII or another lead.
+const TOO_SHORT: u8 = 1 << 0;
+// A continuation after ASCII.
+const TOO_LONG: u8 = 1 << 1;
+const OVERLONG_3: u8 = 1 << 2;
+// Above U+10FFFF.
+const TOO_LARGE: u8 = 1 << 3;
+const SURROGATE: u8 = 1 << 4;
+const OVERLONG_2: u8 = 1 << 5;
+// F5 to FF followed by 80 to 8F, or an overlong F0 80 to F0 8F. They never share a lead, so they share a bit.
+const TOO_LARGE_1000: u8 = 1 << 6;
+const OVERLONG_4: u8 = 1 << 6;
+const TWO_CONTS: u8 = 1 << 7;
+// The classes told by the high nibble of the first byte alone.
+const CARRY: u8 = TOO_SHORT | TOO_LONG | TWO_CONTS;
+
+const BYTE_1_HIGH: [u8; 16] = [
+    // 0xxx: ASCII
+    TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG,
+    // 10xx: continuation
+    TWO_CONTS, TWO_CONTS, TWO_CONTS, TWO_CONTS,
+    // 1100, 1101: lead of 2
+    TOO_SHORT | OVERLONG_2,
+    TOO_SHORT,
+    // 1110: lead of 3
+    TOO_SHORT | OVERLONG_3 | SURROGATE,
+    // 1111: lead of 4, or not a lead
+    TOO_SHORT | TOO_LARGE | TOO_LARGE_1000 | OVERLONG_4,
+];
+const BYTE_1_LOW: [u8; 16] = [
+    CARRY | OVERLONG_3 | OVERLONG_2 | OVERLONG_4,
+    CARRY | OVERLONG_2,
+    CARRY,
+    CARRY,
+    CARRY | TOO_LARGE,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000 | SURROGATE,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+];
+const BYTE_2_HIGH: [u8; 16] = [
+    // 0xxx: ASCII
+    TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT,
+    // 1000, 1001, 101x: continuation
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE_1000 | OVERLONG_4,
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE,
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    // 11xx: lead
+    TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT,
+];
+
+/// Returns true when the 64 bytes from ptr are valid UTF-8 after prev, the 3 bytes before them.
+/// A sequence may be unfinished at the end, the next block checks it.
+#[inline(always)]
+unsafe fn utf8_block<V: Vector>(ptr: *const u8, prev: [u8; 3]) -> bool {
+    let lanes = BLOCK_BYTE_SIZE / V::BYTES;
+    let mut high_bits = V::zero();
+    for lane in 0..lanes {
+        high_bits = high_bits.or(V::load(ptr.add(lane * V::BYTES)));
+    }
+    // Most blocks are ASCII, and they are valid when no sequence before them is unfinished.
+    if high_bits.movemask() == 0 && prev[2] < 0xC0 && prev[1] < 0xE0 && prev[0] < 0xF0 {
+        return true;
+    }
+    // The block is copied after prev, so the bytes before every lane can be loaded like the lane.
+    let mut bytes = [0; 3 + BLOCK_BYTE_SIZE];
+    bytes[..3].copy_from_slice(&prev);
+    core::ptr::copy_nonoverlapping(ptr, bytes.as_mut_ptr().add(3), BLOCK_BYTE_SIZE);
+    let mut errors = V::zero();
+    for lane in 0..lanes {
+        errors = errors.or(utf8_errors::<V>(bytes.as_ptr().add(3 + lane * V::BYTES)));
+    }
+    errors.cmpeq(V::zero()).movemask() == u64::MAX >> (64 - V::BYTES)
+}
+
+/// Nonzero for the bytes from ptr that are not valid UTF-8 after the 3 bytes before ptr, which are read too.
+#[inline(always)]
+unsafe fn utf8_errors<V: Vector>(ptr: *const u8) -> V {
+    let input = V::load(ptr);
+    let prev1 = V::load(ptr.sub(1));
+    let special = prev1.high_nibbles().lookup16(&BYTE_1_HIGH)
+        .and(prev1.and(V::splat(0x0F)).lookup16(&BYTE_1_LOW))
+        .and(input.high_nibbles().lookup16(&BYTE_2_HIGH));
+    // The top bit is set for a byte that must be a continuation because a lead of 3 or 4 is 2 or 3 bytes before it.
+    let third = V::load(ptr.sub(2)).sub_sat(V::splat(0xE0 - 0x80));
+    let fourth = V::load(ptr.sub(3)).sub_sat(V::splat(0xF0 - 0x80));
+    let must_continue = third.or(fourth).and(V::splat(0x80));
+    must_continue.xor(special)
+}
+
+#[inline(always)]
+unsafe fn splat_all<V: Vector, const N: usize>(needles: [u8; N]) -> [V; N] {
+    needles.map(|needle| V::splat(needle))
+}
+
+/// 0xFF for the bytes of chunk that equal one of vn.
+#[inline(always)]
+unsafe fn any_eq<V: Vector, const N: usize>(chunk: V, vn: &[V; N]) -> V {
+    let mut eq = chunk.cmpeq(vn[0]);
+    for &v in &vn[1..] {
+        eq = eq.or(chunk.cmpeq(v));
+    }
+    eq
+}
+
+/// Mask of the 64 bytes from ptr that equal one of vn.
+#[inline(always)]
+unsafe fn any_mask<V: Vector, const N: usize>(ptr: *const u8, vn: &[V; N]) -> u64 {
+    let mut mask = 0;
+    for lane in 0..BLOCK_BYTE_SIZE / V::BYTES {
+        mask |= any_eq(V::load(ptr.add(lane * V::BYTES)), vn).movemask() << (lane * V::BYTES);
+    }
+    mask
+}
+
+#[inline(always)]
+fn distance(a: *const u8, b: *const u8) -> usize {
+    debug_assert!(a >= b);
+    (a as usize) - (b as usize)
+}
@@ -0,0 +1,1039 @@
+/*!
+Field splitter for CSV ([RFC 4180](https://www.rfc-editor.org/rfc/rfc4180)).
+
+The delimiters, line feeds and quotes are found with `BufchrCSV`, so only those positions are looked at.
+Fields are not copied: a quoted field is returned without its surrounding quotes, but doubled quotes (`""`)
+inside it are left as they are. `Fields::utf8` returns them as `str`, checked while the positions are searched,
+and `Fields::unescaped`(with the `alloc` feature) unescapes the doubled quotes the positions went through.
+
+`validate`(with the `alloc` feature) walks the same positions and reports every problem with its location,
+and `sniff` guesses the `CsvDialect` of a file from its first bytes.
+
+A UTF-8 byte order mark is skipped. UTF-16 can not be split as bytes, so it is detected by its byte order mark
+and has to be transcoded with `to_utf8` first.
+*/
+
+#![forbid(unsafe_code)]
+
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "alloc")]
+use core::iter::Peekable;
+
+#[cfg(feature = "alloc")]
+use crate::bufchr::{self, Bufchr};
+use crate::bufchr::{Backend, BufchrCSV, DefaultKernels, DynamicKernels, Kernels};
+
+/// Encoding of a CSV haystack, told by its byte order mark.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// UTF-8, or any other ASCII compatible encoding, without a byte order mark.
+    Utf8,
+    /// UTF-8 after the byte order mark `EF BB BF`, which is not part of the first field.
+    Utf8Bom,
+    /// UTF-16 little endian, after `FF FE`.
+    Utf16Le,
+    /// UTF-16 big endian, after `FE FF`.
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Returns the encoding of haystack from its first bytes.
+    ///
+    /// ```
+    /// use bufchr::csv::Encoding;
+    ///
+    /// assert_eq!(Encoding::detect(b"\xEF\xBB\xBFa,b"), Encoding::Utf8Bom);
+    /// assert_eq!(Encoding::detect(b"\xFF\xFEa\0"), Encoding::Utf16Le);
+    /// assert_eq!(Encoding::detect(b"a,b"), Encoding::Utf8);
+    /// ```
+    pub fn detect(haystack: &[u8]) -> Encoding {
+        if haystack.starts_with(b"\xEF\xBB\xBF") {
+            Encoding::Utf8Bom
+        }
+        else if haystack.starts_with(b"\xFF\xFE") {
+            Encoding::Utf16Le
+        }
+        else if haystack.starts_with(b"\xFE\xFF") {
+            Encoding::Utf16Be
+        }
+        else {
+            Encoding::Utf8
+        }
+    }
+
+    /// Length of the byte order mark.
+    pub fn bom_len(self) -> usize {
+        match self {
+            Encoding::Utf8 => 0,
+            Encoding::Utf8Bom => 3,
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+        }
+    }
+
+    /// UTF-8 with or without a byte order mark, which the CSV readers can split.
+    pub fn is_utf8(self) -> bool {
+        matches!(self, Encoding::Utf8 | Encoding::Utf8Bom)
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        })
+    }
+}
+
+/// UTF-16 that `to_utf8` could not transcode, or UTF-8 that `StrFields` found invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingError {
+    pub encoding: Encoding,
+    /// Byte offset of the unpaired surrogate, or of the last byte when the length is odd.
+    /// For UTF-8, the offset of the first invalid byte, like `core::str::Utf8Error::valid_up_to`.
+    pub offset: usize,
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} at byte {}", self.encoding, self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodingError {}
+
+/// Returns haystack as UTF-8 without a byte order mark. UTF-16 is transcoded,
+/// and anything else is borrowed after a UTF-8 byte order mark. UTF-8 is not validated.
+///
+/// ```
+/// use bufchr::csv::to_utf8;
+///
+/// assert_eq!(to_utf8(b"\xEF\xBB\xBFa,b").unwrap(), &b"a,b"[..]);
+/// assert_eq!(to_utf8(b"\xFE\xFF\0a\0,\0\xE9").unwrap(), "a,\u{e9}".as_bytes());
+/// assert_eq!(to_utf8(b"\xFF\xFEa\0\0\xD8").unwrap_err().offset, 4);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn to_utf8(haystack: &[u8]) -> Result<Cow<'_, [u8]>, EncodingError> {
+    let encoding = Encoding::detect(haystack);
+    let data = &haystack[encoding.bom_len()..];
+    let unit: fn([u8; 2]) -> u16 = match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => return Ok(Cow::Borrowed(data)),
+        Encoding::Utf16Le => u16::from_le_bytes,
+        Encoding::Utf16Be => u16::from_be_bytes,
+    };
+    let pairs = data.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(EncodingError { encoding, offset: haystack.len() - 1 });
+    }
+    let mut utf8 = Vec::with_capacity(data.len() / 2 * 3);
+    // Offset of the next code unit.
+    let mut offset = encoding.bom_len();
+    let units = pairs.map(|pair| unit([pair[0], pair[1]]));
+    for c in core::char::decode_utf16(units) {
+        let c = c.map_err(|_| EncodingError { encoding, offset })?;
+        offset += c.len_utf16() * 2;
+        utf8.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+    Ok(Cow::Owned(utf8))
+}
+
+/// How the records of a CSV haystack end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Terminator {
+    /// `\n`.
+    Lf,
+    /// `\r\n`. It is read like `Lf`, because a `\r` before the line feed is never part of the last field.
+    CrLf,
+    /// `\r` alone.
+    Cr,
+}
+
+/// How a CSV haystack is written.
+///
+/// ```
+/// use bufchr::csv::{CsvDialect, Terminator};
+///
+/// let dialect = CsvDialect::new(b';').quote(b'\'').terminator(Terminator::CrLf).header(true);
+/// assert_eq!((dialect.delimiter, dialect.quote, dialect.line_end()), (b';', b'\'', b'\n'));
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CsvDialect {
+    /// Byte between the fields of a record.
+    pub delimiter: u8,
+    /// Byte that encloses a field, in which it is written twice.
+    pub quote: u8,
+    /// How records end.
+    pub terminator: Terminator,
+    /// The first record names the columns. It is still read like any other record.
+    pub header: bool,
+}
+
+impl CsvDialect {
+    /// A dialect with delimiter, `"` quotes, `\n` line ends and no header, as in RFC 4180.
+    pub fn new(delimiter: u8) -> CsvDialect {
+        CsvDialect { delimiter, quote: b'"', terminator: Terminator::Lf, header: false }
+    }
+
+    pub fn quote(mut self, quote: u8) -> CsvDialect {
+        self.quote = quote;
+        self
+    }
+
+    pub fn terminator(mut self, terminator: Terminator) -> CsvDialect {
+        self.terminator = terminator;
+        self
+    }
+
+    pub fn header(mut self, header: bool) -> CsvDialect {
+        self.header = header;
+        self
+    }
+
+    /// The byte that ends a record: `\r` for `Terminator::Cr`, otherwise `\n`.
+    pub fn line_end(&self) -> u8 {
+        match self.terminator {
+            Terminator::Lf | Terminator::CrLf => b'\n',
+            Terminator::Cr => b'\r',
+        }
+    }
+
+    fn assert_valid(&self) {
+        let line_end = self.line_end();
+        assert!(self.delimiter != line_end && self.delimiter != self.quote && self.quote != line_end,
+            "delimiter, quote and line end must be different bytes");
+    }
+}
+
+impl Default for CsvDialect {
+    fn default() -> CsvDialect {
+        CsvDialect::new(b',')
+    }
+}
+
+/// One field of a CSV haystack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    /// Byte offset of the first byte of the field, including an opening quote.
+    pub offset: usize,
+    /// The field without surrounding quotes, the line feed and a trailing carriage return.
+    pub data: &'a [u8],
+    /// The field was enclosed in quotes.
+    pub quoted: bool,
+    /// The field is the last one of its record.
+    pub last: bool,
+}
+
+/// A field of `StrFields`: a `Field` whose data is valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrField<'a> {
+    /// Byte offset of the first byte of the field, including an opening quote.
+    pub offset: usize,
+    /// The field without surrounding quotes, the line feed and a trailing carriage return.
+    pub data: &'a str,
+    /// The field was enclosed in quotes.
+    pub quoted: bool,
+    /// The field is the last one of its record.
+    pub last: bool,
+}
+
+/// Iterator over the fields of a CSV haystack, in order and record by record.
+///
+/// Records end with `\n` or `\r\n`(or the line end of the dialect), and a line feed inside quotes belongs to the field.
+/// Blank lines are skipped. A quote that does not open a field is kept as data.
+/// A UTF-8 byte order mark is skipped, and a UTF-16 haystack has no fields(see `encoding`).
+///
+/// ```
+/// use bufchr::csv::Fields;
+///
+/// let haystack = b"a,\"b,\"\"c\"\"\"\r\n\n1,2";
+/// let fields: Vec<(&[u8], bool)> = Fields::new(haystack, b',').map(|f| (f.data, f.last)).collect();
+/// assert_eq!(fields, vec![
+///     (&b"a"[..], false), (&b"b,\"\"c\"\""[..], true),
+///     (&b"1"[..], false), (&b"2"[..], true),
+/// ]);
+/// ```
+pub struct Fields<'a, K: Kernels = DefaultKernels> {
+    haystack: &'a [u8],
+    positions: BufchrCSV<'a, K>,
+    delimiter: u8,
+    line_end: u8,
+    quote: u8,
+    encoding: Encoding,
+    // Offset of the current field.
+    start: usize,
+    // Position of the quote that closed the current field, if it was quoted.
+    close_quote: Option<usize>,
+    // Positions of the second quote of every doubled quote of the current field, when they are recorded for `Unescaped`.
+    #[cfg(feature = "alloc")]
+    escapes: Option<Vec<usize>>,
+    done: bool,
+}
+
+impl<'a> Fields<'a> {
+    /// Fields of haystack separated by delimiter, which must not be `\n` or `"`.
+    pub fn new(haystack: &'a [u8], delimiter: u8) -> Fields<'a> {
+        Fields::with_dialect(haystack, CsvDialect::new(delimiter))
+    }
+
+    /// Fields of haystack written in dialect, e.g. one returned by `sniff`.
+    pub fn with_dialect(haystack: &'a [u8], dialect: CsvDialect) -> Fields<'a> {
+        Fields::with_kernels(haystack, dialect, crate::bufchr::default_kernels())
+    }
+}
+
+impl<'a> Fields<'a, DynamicKernels> {
+    /// Like `with_dialect`, but uses the kernels of backend. None is returned when backend is not available.
+    pub fn with_backend(haystack: &'a [u8], dialect: CsvDialect, backend: Backend) -> Option<Fields<'a, DynamicKernels>> {
+        Some(Fields::with_kernels(haystack, dialect, crate::bufchr::dynamic_kernels(backend)?))
+    }
+}
+
+impl<'a, K: Kernels> Fields<'a, K> {
+    /// Like `with_dialect`, but uses kernels.
+    fn with_kernels(haystack: &'a [u8], dialect: CsvDialect, kernels: K) -> Fields<'a, K> {
+        dialect.assert_valid();
+        let encoding = Encoding::detect(haystack);
+        Fields {
+            haystack,
+            positions: BufchrCSV::with_kernels(haystack, dialect, kernels),
+            delimiter: dialect.delimiter,
+            line_end: dialect.line_end(),
+            quote: dialect.quote,
+            encoding,
+            start: encoding.bom_len(),
+            close_quote: None,
+            #[cfg(feature = "alloc")]
+            escapes: None,
+            done: !encoding.is_utf8(),
+        }
+    }
+
+    /// When detect is false, haystack is read as UTF-8 even if it starts like a byte order mark,
+    /// e.g. for a chunk of a stream that does not start at the beginning of the file. Call it before the first field.
+    pub fn detect_encoding(mut self, detect: bool) -> Fields<'a, K> {
+        if !detect && self.start == self.encoding.bom_len() {
+            self.encoding = Encoding::Utf8;
+            self.start = 0;
+            self.done = false;
+        }
+        self
+    }
+
+    /// The encoding told by the byte order mark of haystack. The fields of UTF-16 are not read.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Returns the fields as `str`. The haystack is checked to be UTF-8 by the kernels that search the positions,
+    /// so it is not read a second time. The delimiter and the quote must be ASCII. Call it before the first field.
+    ///
+    /// ```
+    /// use bufchr::csv::Fields;
+    ///
+    /// let mut fields = Fields::new("name,city\nZoë,Zürich\n".as_bytes(), b',').utf8();
+    /// assert_eq!(fields.nth(3).unwrap().unwrap().data, "Zürich");
+    ///
+    /// let mut fields = Fields::new(b"a,b\n\xFF,c\n", b',').utf8();
+    /// assert_eq!(fields.nth(2).unwrap().unwrap_err().offset, 4);
+    /// assert!(fields.next().is_none());
+    /// ```
+    pub fn utf8(mut self) -> StrFields<'a, K> {
+        assert!(self.delimiter.is_ascii() && self.quote.is_ascii(), "the delimiter and the quote must be ASCII");
+        self.positions = self.positions.validate_utf8(true);
+        StrFields { fields: self, failed: false }
+    }
+
+    /// Returns the fields with the doubled quotes of quoted fields unescaped. Their positions are recorded
+    /// while the field is skipped, so the field is not scanned again, and a field without any is borrowed.
+    /// A field with doubled quotes is owned: unless its data is given back with `Unescaped::recycle`,
+    /// every such field allocates. Call it before the first field.
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use bufchr::csv::Fields;
+    ///
+    /// let mut fields = Fields::new(b"a,\"say \"\"hi\"\"\"\n", b',').unescaped();
+    /// assert_eq!(fields.next().unwrap().data, Cow::Borrowed(&b"a"[..]));
+    /// let field = fields.next().unwrap();
+    /// assert_eq!(&*field.data, b"say \"hi\"");
+    /// // The buffer of the field is reused by the next one with doubled quotes.
+    /// fields.recycle(field.data);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn unescaped(mut self) -> Unescaped<'a, K> {
+        self.escapes = Some(Vec::new());
+        Unescaped { fields: self, buffers: Vec::new() }
+    }
+
+    /// Offset where the next field starts. After the last field of a record, it is the offset of the next line,
+    /// which is `haystack.len() + 1` when the record was ended by the end of haystack instead of a line feed.
+    /// A reader of a stream can keep the bytes from there for the next chunk.
+    pub fn position(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the field from self.start to end and starts the next one after end.
+    fn field(&mut self, end: usize, last: bool) -> Field<'a> {
+        let start = self.start;
+        let mut data_end = end;
+        if last && self.line_end == b'\n' && data_end > start && self.haystack[data_end - 1] == b'\r' {
+            data_end -= 1;
+        }
+        let quoted = self.close_quote == Some(data_end.wrapping_sub(1)) && data_end - start >= 2;
+        let data = if quoted {
+            &self.haystack[start + 1..data_end - 1]
+        }
+        else {
+            &self.haystack[start..data_end]
+        };
+        self.start = end + 1;
+        self.close_quote = None;
+        Field { offset: start, data, quoted, last }
+    }
+
+    /// Skips the positions up to the quote that closes the field opened at self.start.
+    /// Returns false when the haystack ends inside the quotes.
+    fn skip_quoted(&mut self) -> bool {
+        #[cfg(feature = "alloc")]
+        {
+            if let Some(escapes) = &mut self.escapes {
+                escapes.clear();
+            }
+        }
+        while let Some(pos) = self.positions.next() {
+            if self.haystack[pos] != self.quote {
+                continue;
+            }
+            if self.haystack.get(pos + 1) == Some(&self.quote) {
+                // An escaped quote. Its second quote is the next position.
+                #[cfg(feature = "alloc")]
+                {
+                    if let Some(escapes) = &mut self.escapes {
+                        escapes.push(pos + 1);
+                    }
+                }
+                self.positions.next();
+                continue;
+            }
+            self.close_quote = Some(pos);
+            return true;
+        }
+        false
+    }
+
+    fn is_blank_line(&self, end: usize) -> bool {
+        let line = &self.haystack[self.start..end];
+        line.is_empty() || (line == b"\r" && self.line_end == b'\n')
+    }
+}
+
+impl<'a, K: Kernels> Iterator for Fields<'a, K> {
+    type Item = Field<'a>;
+
+    fn next(&mut self) -> Option<Field<'a>> {
+        if self.done {
+            return None;
+        }
+        // True when the current field follows a delimiter, so it exists even if it is empty.
+        let mut in_record = self.start > self.encoding.bom_len() && self.haystack[self.start - 1] == self.delimiter;
+        while let Some(pos) = self.positions.next() {
+            let b = self.haystack[pos];
+            if b == self.quote {
+                if pos == self.start && !self.skip_quoted() {
+                    break;
+                }
+                continue;
+            }
+            if b == self.delimiter {
+                return Some(self.field(pos, false));
+            }
+            // A line end.
+            if !in_record && self.is_blank_line(pos) {
+                self.start = pos + 1;
+                continue;
+            }
+            return Some(self.field(pos, true));
+        }
+        self.done = true;
+        let len = self.haystack.len();
+        if self.start < len && !self.is_blank_line(len) {
+            in_record = true;
+        }
+        if in_record {
+            return Some(self.field(len, true));
+        }
+        None
+    }
+}
+
+/// Iterator over the fields of a CSV haystack as `str`, returned by `Fields::utf8`.
+///
+/// The first field that is not valid UTF-8, or that follows the first invalid byte, is an error, and it is the last item.
+pub struct StrFields<'a, K: Kernels = DefaultKernels> {
+    fields: Fields<'a, K>,
+    failed: bool,
+}
+
+impl<'a, K: Kernels> StrFields<'a, K> {
+    /// The encoding told by the byte order mark of haystack. The fields of UTF-16 are not read.
+    pub fn encoding(&self) -> Encoding {
+        self.fields.encoding()
+    }
+
+    /// Offset where the next field starts, see `Fields::position`.
+    pub fn position(&self) -> usize {
+        self.fields.position()
+    }
+}
+
+impl<'a, K: Kernels> Iterator for StrFields<'a, K> {
+    type Item = Result<StrField<'a>, EncodingError>;
+
+    fn next(&mut self) -> Option<Result<StrField<'a>, EncodingError>> {
+        if self.failed {
+            return None;
+        }
+        let field = self.fields.next()?;
+        // The positions have moved past the field, so its bytes are checked.
+        let start = field.offset + field.quoted as usize;
+        match self.fields.positions.get_str(start, start + field.data.len()) {
+            Some(data) => Some(Ok(StrField { offset: field.offset, data, quoted: field.quoted, last: field.last })),
+            None => {
+                self.failed = true;
+                let offset = self.fields.positions.utf8_error().unwrap_or(start);
+                Some(Err(EncodingError { encoding: Encoding::Utf8, offset }))
+            }
+        }
+    }
+}
+
+/// A field of `Unescaped`: a `Field` whose doubled quotes are unescaped.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnescapedField<'a> {
+    /// Byte offset of the first byte of the field, including an opening quote.
+    pub offset: usize,
+    /// The field without surrounding quotes, the line feed and a trailing carriage return.
+    /// It is borrowed from the haystack unless the field had doubled quotes.
+    pub data: Cow<'a, [u8]>,
+    /// The field was enclosed in quotes.
+    pub quoted: bool,
+    /// The field is the last one of its record.
+    pub last: bool,
+}
+
+/// Iterator over the fields of a CSV haystack with doubled quotes unescaped, returned by `Fields::unescaped`.
+///
+/// A field with doubled quotes is copied without their second quotes into a buffer of a pool.
+/// `recycle` gives the buffer of a field back to the pool, so once the buffers of the fields of a record are recycled,
+/// the next records are unescaped without allocating.
+#[cfg(feature = "alloc")]
+pub struct Unescaped<'a, K: Kernels = DefaultKernels> {
+    fields: Fields<'a, K>,
+    // Buffers given back by recycle.
+    buffers: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, K: Kernels> Unescaped<'a, K> {
+    /// The encoding told by the byte order mark of haystack. The fields of UTF-16 are not read.
+    pub fn encoding(&self) -> Encoding {
+        self.fields.encoding()
+    }
+
+    /// Offset where the next field starts, see `Fields::position`.
+    pub fn position(&self) -> usize {
+        self.fields.position()
+    }
+
+    /// Keeps the buffer of data, when it is owned, for a next field with doubled quotes.
+    pub fn recycle(&mut self, data: Cow<'a, [u8]>) {
+        if let Cow::Owned(buffer) = data {
+            self.buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, K: Kernels> Iterator for Unescaped<'a, K> {
+    type Item = UnescapedField<'a>;
+
+    fn next(&mut self) -> Option<UnescapedField<'a>> {
+        let field = self.fields.next()?;
+        let escapes = self.fields.escapes.as_deref().unwrap_or_default();
+        let data = if !field.quoted || escapes.is_empty() {
+            Cow::Borrowed(field.data)
+        }
+        else {
+            // Copies the bytes between the second quotes of the pairs.
+            let mut data = self.buffers.pop().unwrap_or_default();
+            data.clear();
+            let mut start = field.offset + 1;
+            for &escape in escapes {
+                data.extend_from_slice(&self.fields.haystack[start..escape]);
+                start = escape + 1;
+            }
+            data.extend_from_slice(&self.fields.haystack[start..field.offset + 1 + field.data.len()]);
+            Cow::Owned(data)
+        };
+        Some(UnescapedField { offset: field.offset, data, quoted: field.quoted, last: field.last })
+    }
+}
+
+/// A problem found by `validate`.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvErrorKind {
+    /// A quoted field is not closed before the end of the haystack. Nothing after its opening quote is checked.
+    UnclosedQuote,
+    /// A quote inside a field that does not start with one.
+    StrayQuote,
+    /// Bytes between the closing quote of a field and the next delimiter or line end.
+    TextAfterQuote,
+    /// A record has another number of fields than the first one.
+    FieldCount {
+        expected: usize,
+        found: usize,
+    },
+    /// A `\r` outside of quotes that is not followed by `\n`. Not checked with `Terminator::Cr`.
+    BareCarriageReturn,
+    /// The haystack starts with a UTF-16 byte order mark and has to be transcoded with `to_utf8`.
+    /// Nothing else is checked.
+    UnsupportedEncoding(Encoding),
+}
+
+impl fmt::Display for CsvErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CsvErrorKind::UnclosedQuote => write!(f, "quoted field is not closed"),
+            CsvErrorKind::StrayQuote => write!(f, "quote in an unquoted field"),
+            CsvErrorKind::TextAfterQuote => write!(f, "text after the closing quote"),
+            CsvErrorKind::FieldCount { expected, found } => write!(f, "{} fields, expected {}", found, expected),
+            CsvErrorKind::BareCarriageReturn => write!(f, "carriage return without a line feed"),
+            CsvErrorKind::UnsupportedEncoding(encoding) => write!(f, "{} is not supported, transcode it to UTF-8", encoding),
+        }
+    }
+}
+
+/// A problem of a CSV haystack and where it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvError {
+    pub kind: CsvErrorKind,
+    /// 1-based record number. Blank lines are not records.
+    pub record: usize,
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based field number in the record.
+    pub column: usize,
+    /// Byte offset of the problem. A record with too few fields points at its line end,
+    /// and one with too many at its first extra field.
+    pub offset: usize,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record {}, line {}, column {} (byte {}): {}", self.record, self.line, self.column, self.offset, self.kind)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CsvError {}
+
+/// Returns the problems of haystack in order of their offset. Records are split like `Fields` does,
+/// and the field count of every record is compared with the one of the first record.
+/// Offsets count a UTF-8 byte order mark, which is skipped.
+///
+/// ```
+/// use bufchr::csv::{validate, CsvDialect, CsvErrorKind};
+///
+/// let haystack = b"a,b\n1,x\"y\n\"2\"z,3,4\r\r\n";
+/// let errors: Vec<_> = validate(haystack, CsvDialect::default()).iter()
+///     .map(|e| (e.kind, e.record, e.line, e.column, e.offset)).collect();
+/// assert_eq!(errors, vec![
+///     (CsvErrorKind::StrayQuote, 2, 2, 2, 7),
+///     (CsvErrorKind::TextAfterQuote, 3, 3, 1, 13),
+///     (CsvErrorKind::FieldCount { expected: 2, found: 3 }, 3, 3, 3, 17),
+///     (CsvErrorKind::BareCarriageReturn, 3, 3, 3, 18),
+/// ]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn validate(haystack: &[u8], dialect: CsvDialect) -> Vec<CsvError> {
+    validate_with_kernels(haystack, dialect, bufchr::default_kernels())
+}
+
+/// Like `validate`, but uses the kernels of backend. None is returned when backend is not available.
+#[cfg(feature = "alloc")]
+pub fn validate_with_backend(haystack: &[u8], dialect: CsvDialect, backend: Backend) -> Option<Vec<CsvError>> {
+    Some(validate_with_kernels(haystack, dialect, bufchr::dynamic_kernels(backend)?))
+}
+
+#[cfg(feature = "alloc")]
+fn validate_with_kernels<K: Kernels>(haystack: &[u8], dialect: CsvDialect, kernels: K) -> Vec<CsvError> {
+    dialect.assert_valid();
+    let encoding = Encoding::detect(haystack);
+    if !encoding.is_utf8() {
+        let kind = CsvErrorKind::UnsupportedEncoding(encoding);
+        return alloc::vec![CsvError { kind, record: 1, line: 1, column: 1, offset: 0 }];
+    }
+    let line_end = dialect.line_end();
+    // A `\r` is the line end itself with Terminator::Cr, so there are none to check.
+    let carriage_returns = if line_end == b'\n' { haystack } else { &[] };
+    let mut validator = Validator {
+        haystack,
+        delimiter: dialect.delimiter,
+        line_end,
+        quote: dialect.quote,
+        positions: BufchrCSV::with_kernels(haystack, dialect, kernels),
+        carriage_returns: Bufchr::with_kernels(carriage_returns, b'\r', kernels).peekable(),
+        errors: Vec::new(),
+        record: 1,
+        line: 1,
+        column: 1,
+        start: encoding.bom_len(),
+        quotes: None,
+        reported: false,
+        expected: None,
+        extra: None,
+    };
+    validator.run();
+    validator.errors.sort_by_key(|error| error.offset);
+    validator.errors
+}
+
+#[cfg(feature = "alloc")]
+struct Validator<'a, K: Kernels> {
+    haystack: &'a [u8],
+    delimiter: u8,
+    line_end: u8,
+    quote: u8,
+    positions: BufchrCSV<'a, K>,
+    // Carriage returns are rare, so they are searched on their own and checked when their field ends.
+    carriage_returns: Peekable<Bufchr<'a, K>>,
+    errors: Vec<CsvError>,
+    record: usize,
+    line: usize,
+    column: usize,
+    // Offset of the current field.
+    start: usize,
+    // Opening and closing quote of the current field, if it was quoted.
+    quotes: Option<(usize, usize)>,
+    // A quote problem of the current field was reported, so its other quotes are not.
+    reported: bool,
+    // Number of fields of the first record.
+    expected: Option<usize>,
+    // Offset and line of the first field after the expected ones in the current record.
+    extra: Option<(usize, usize)>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, K: Kernels> Validator<'a, K> {
+    fn run(&mut self) {
+        while let Some(pos) = self.positions.next() {
+            let b = self.haystack[pos];
+            if b == self.quote {
+                if pos == self.start {
+                    if !self.skip_quoted(pos) {
+                        return;
+                    }
+                }
+                else if !self.reported {
+                    self.reported = true;
+                    self.error(CsvErrorKind::StrayQuote, pos);
+                }
+            }
+            else if b == self.delimiter {
+                self.end_field(pos, false);
+            }
+            else if self.column == 1 && self.is_blank_line(pos) {
+                self.check_carriage_returns(pos);
+                self.start = pos + 1;
+                self.line += 1;
+            }
+            else {
+                self.end_field(pos, true);
+                self.line += 1;
+            }
+        }
+        let len = self.haystack.len();
+        if self.column > 1 || !self.is_blank_line(len) {
+            self.end_field(len, true);
+        }
+        self.check_carriage_returns(len);
+    }
+
+    /// Skips the positions up to the quote that closes the field opened at open.
+    /// Returns false when the haystack ends inside the quotes.
+    fn skip_quoted(&mut self, open: usize) -> bool {
+        let line = self.line;
+        while let Some(pos) = self.positions.next() {
+            let b = self.haystack[pos];
+            if b == self.line_end {
+                self.line += 1;
+            }
+            else if b == self.quote && self.haystack.get(pos + 1) == Some(&self.quote) {
+                self.positions.next();
+            }
+            else if b == self.quote {
+                self.quotes = Some((open, pos));
+                match self.haystack.get(pos + 1) {
+                    None | Some(b'\r') => {}
+                    Some(&b) if b == self.delimiter || b == self.line_end => {}
+                    Some(_) => {
+                        self.reported = true;
+                        self.error(CsvErrorKind::TextAfterQuote, pos + 1);
+                    }
+                }
+                return true;
+            }
+        }
+        self.check_carriage_returns(open);
+        self.line = line;
+        self.error(CsvErrorKind::UnclosedQuote, open);
+        false
+    }
+
+    /// Ends the current field at end, which is its delimiter or line end.
+    fn end_field(&mut self, end: usize, last: bool) {
+        self.check_carriage_returns(end);
+        if self.expected.map(|expected| expected + 1) == Some(self.column) {
+            self.extra = Some((self.start, self.line));
+        }
+        if last {
+            match self.expected {
+                None => self.expected = Some(self.column),
+                Some(expected) if expected != self.column => {
+                    let kind = CsvErrorKind::FieldCount { expected, found: self.column };
+                    let (column, offset, line) = match self.extra {
+                        Some((offset, line)) => (expected + 1, offset, line),
+                        None => (self.column + 1, end, self.line),
+                    };
+                    self.errors.push(CsvError { kind, record: self.record, line, column, offset });
+                }
+                Some(_) => {}
+            }
+            self.record += 1;
+            self.column = 1;
+            self.extra = None;
+        }
+        else {
+            self.column += 1;
+        }
+        self.start = end + 1;
+        self.quotes = None;
+        self.reported = false;
+    }
+
+    /// Reports the carriage returns before end that are not in the quotes of the current field
+    /// and not followed by a line feed.
+    fn check_carriage_returns(&mut self, end: usize) {
+        while let Some(&pos) = self.carriage_returns.peek() {
+            if pos >= end {
+                break;
+            }
+            self.carriage_returns.next();
+            let quoted = matches!(self.quotes, Some((open, close)) if open < pos && pos < close);
+            if !quoted && self.haystack.get(pos + 1) != Some(&b'\n') {
+                self.error(CsvErrorKind::BareCarriageReturn, pos);
+            }
+        }
+    }
+
+    fn is_blank_line(&self, end: usize) -> bool {
+        let line = &self.haystack[self.start.min(end)..end];
+        line.is_empty() || (line == b"\r" && self.line_end == b'\n')
+    }
+
+    fn error(&mut self, kind: CsvErrorKind, offset: usize) {
+        self.errors.push(CsvError { kind, record: self.record, line: self.line, column: self.column, offset });
+    }
+}
+
+/// Delimiters tried by `sniff`, in order of preference.
+#[cfg(feature = "alloc")]
+const DELIMITERS: [u8; 4] = [b',', b';', b'\t', b'|'];
+/// Records after the first one that `sniff` compares with it to find a header.
+#[cfg(feature = "alloc")]
+const HEADER_SAMPLE_RECORDS: usize = 20;
+
+/// Guesses the dialect of a CSV file from sample, usually its first 64KB.
+///
+/// * the terminator is `\r\n` when most line feeds follow a `\r`, and `\r` when there is no line feed.
+/// * the quote is `'` when it starts or ends more fields than `"` does.
+/// * the delimiter is the one of `,`, `;`, `\t` and `|` that gives the most records with the same number
+///   of fields(at least 2). Candidates are skipped at once when `count` does not find them in sample.
+/// * there is a header when, in most columns, the first record has another kind than the values below it:
+///   text above numbers, an integer above decimals, or text of another length above texts of one length.
+///
+/// A UTF-8 byte order mark and a record cut by the end of sample are ignored. Without a delimiter that splits
+/// the records, or for UTF-16, the delimiter is `,` and there is no header.
+///
+/// ```
+/// use bufchr::csv::{sniff, Terminator};
+///
+/// let dialect = sniff(b"name;price\r\n'Oil; raw';3,5\r\n'Gas';12,25\r\n'Salt';0,5\r\n");
+/// assert_eq!((dialect.delimiter, dialect.quote), (b';', b'\''));
+/// assert_eq!((dialect.terminator, dialect.header), (Terminator::CrLf, true));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn sniff(sample: &[u8]) -> CsvDialect {
+    let encoding = Encoding::detect(sample);
+    if !encoding.is_utf8() {
+        return CsvDialect::new(b',');
+    }
+    let sample = &sample[encoding.bom_len()..];
+    let terminator = sniff_terminator(sample);
+    let mut dialect = CsvDialect::new(b',').terminator(terminator);
+    let line_end = dialect.line_end();
+    let sample = match sample.iter().rposition(|&b| b == line_end) {
+        Some(pos) => &sample[..pos + 1],
+        None => sample,
+    };
+    dialect.quote = sniff_quote(sample, line_end);
+
+    let mut best = 0;
+    for &delimiter in DELIMITERS.iter() {
+        if delimiter == dialect.quote || bufchr::count(sample, delimiter) == 0 {
+            continue;
+        }
+        let candidate = CsvDialect { delimiter, ..dialect };
+        let score = consistent_records(sample, candidate);
+        if score > best {
+            best = score;
+            dialect = candidate;
+        }
+    }
+    // One column says nothing about a header.
+    dialect.header = best > 0 && has_header(sample, dialect);
+    dialect
+}
+
+#[cfg(feature = "alloc")]
+fn sniff_terminator(sample: &[u8]) -> Terminator {
+    let line_feeds = bufchr::count(sample, b'\n');
+    if line_feeds == 0 {
+        return if bufchr::count(sample, b'\r') > 0 { Terminator::Cr } else { Terminator::Lf };
+    }
+    let crlf = Bufchr::new(sample, b'\n').filter(|&pos| pos > 0 && sample[pos - 1] == b'\r').count();
+    if crlf * 2 > line_feeds { Terminator::CrLf } else { Terminator::Lf }
+}
+
+/// Returns the quote that most often starts a field or ends one: the byte before or after it is a
+/// line end or one of the delimiters.
+#[cfg(feature = "alloc")]
+fn sniff_quote(sample: &[u8], line_end: u8) -> u8 {
+    let is_boundary = |b: Option<&u8>| match b {
+        None => true,
+        Some(&b) => b == line_end || b == b'\r' || DELIMITERS.contains(&b),
+    };
+    let fields = |quote: u8| Bufchr::new(sample, quote)
+        .filter(|&pos| is_boundary(pos.checked_sub(1).map(|i| &sample[i])) || is_boundary(sample.get(pos + 1)))
+        .count();
+    if fields(b'\'') > fields(b'"') { b'\'' } else { b'"' }
+}
+
+/// Returns the number of records with the most common field count, or 0 when that count is 1.
+#[cfg(feature = "alloc")]
+fn consistent_records(sample: &[u8], dialect: CsvDialect) -> usize {
+    let mut counts = Vec::new();
+    let mut fields = 0;
+    for field in Fields::with_dialect(sample, dialect) {
+        fields += 1;
+        if field.last {
+            counts.push(fields);
+            fields = 0;
+        }
+    }
+    counts.sort_unstable();
+    let (mut mode, mut mode_records) = (0, 0);
+    let mut run_start = 0;
+    for i in 1..=counts.len() {
+        if i == counts.len() || counts[i] != counts[run_start] {
+            if i - run_start > mode_records {
+                mode = counts[run_start];
+                mode_records = i - run_start;
+            }
+            run_start = i;
+        }
+    }
+    if mode < 2 { 0 } else { mode_records }
+}
+
+#[cfg(feature = "alloc")]
+fn has_header(sample: &[u8], dialect: CsvDialect) -> bool {
+    let mut records: Vec<Vec<&[u8]>> = Vec::new();
+    let mut record = Vec::new();
+    for field in Fields::with_dialect(sample, dialect) {
+        record.push(field.data);
+        if field.last {
+            records.push(core::mem::take(&mut record));
+            if records.len() > HEADER_SAMPLE_RECORDS {
+                break;
+            }
+        }
+    }
+    let (header, rows) = match records.split_first() {
+        Some((header, rows)) if !rows.is_empty() => (header, rows),
+        _ => return false,
+    };
+    let mut votes = 0;
+    for (column, name) in header.iter().enumerate() {
+        let mut values = rows.iter()
+            .filter(|row| row.len() == header.len() && !row[column].is_empty())
+            .map(|row| ValueKind::of(row[column]));
+        // Columns without values or with values of different kinds do not vote.
+        let kind = values.next().and_then(|first| values.try_fold(first, ValueKind::merge));
+        if let Some(kind) = kind {
+            votes += if ValueKind::of(name) == kind { -1 } else { 1 };
+        }
+    }
+    votes > 0
+}
+
+/// Kind of a value for the header check of `sniff`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    Integer,
+    /// Any other decimal number like `3.5`, `-1e6` or `0,25`.
+    Decimal,
+    /// Text of a length.
+    Text(usize),
+}
+
+#[cfg(feature = "alloc")]
+impl ValueKind {
+    fn of(value: &[u8]) -> ValueKind {
+        let digits = value.strip_prefix(b"-").unwrap_or(value);
+        if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) {
+            return ValueKind::Integer;
+        }
+        let mut buf = [0; 64];
+        if value.len() <= buf.len() && value.iter().any(u8::is_ascii_digit) {
+            for (b, out) in value.iter().zip(buf.iter_mut()) {
+                *out = if *b == b',' { b'.' } else { *b };
+            }
+            if let Ok(Ok(_)) = core::str::from_utf8(&buf[..value.len()]).map(|s| s.trim().parse::<f64>()) {
+                return ValueKind::Decimal;
+            }
+        }
+        ValueKind::Text(value.len())
+    }
+
+    /// The kind of a column with values of both kinds: integers are decimals too, texts must have one length.
+    fn merge(self, other: ValueKind) -> Option<ValueKind> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (ValueKind::Integer, ValueKind::Decimal) | (ValueKind::Decimal, ValueKind::Integer) => Some(ValueKind::Decimal),
+            _ => None,
+        }
+    }
+}
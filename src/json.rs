@@ -0,0 +1,95 @@
+/*!
+Structural index of JSON text (stage 1 of [simdjson](https://github.com/simdjson/simdjson)).
+
+Each 64 byte block is classified at once into quotes, backslashes, operators(`{}[]:,`) and whitespace.
+Escaped characters and string regions are tracked with bit operations, also across block boundaries,
+so the structural characters inside strings are removed without looking at each byte again.
+*/
+
+#![forbid(unsafe_code)]
+
+use alloc::vec::Vec;
+
+use crate::bufchr;
+use crate::bufchr::Kernels;
+
+const BLOCK_SIZE: usize = 64;
+const EVEN_BITS: u64 = 0x5555_5555_5555_5555;
+
+/// Returns the positions of the structural characters of haystack in order.
+///
+/// Structural characters are `{}[]:,` outside of strings, the opening quote of every string
+/// and the first byte of every other scalar(number, `true`, `false`, `null`).
+/// This only finds positions, it does not validate JSON. haystack must be shorter than 4GB.
+///
+/// ```
+/// let haystack = br#"{"a": [1, "x,y"]}"#;
+/// assert_eq!(bufchr::json::structural_index(haystack), vec![0, 1, 4, 6, 7, 8, 10, 15, 16]);
+/// ```
+pub fn structural_index(haystack: &[u8]) -> Vec<u32> {
+    assert!(haystack.len() <= u32::MAX as usize);
+    let kernels = bufchr::default_kernels();
+    let mut positions = Vec::with_capacity(haystack.len() / 8);
+    let mut prev_escaped = 0;
+    let mut prev_in_string = 0;
+    let mut prev_scalar = 0;
+
+    for (block_idx, block) in haystack.chunks(BLOCK_SIZE).enumerate() {
+        let [quote, backslash, op, whitespace] = if block.len() == BLOCK_SIZE {
+            kernels.json_block(block)
+        }
+        else {
+            bufchr::fallback::json_block(block)
+        };
+
+        let escaped = find_escaped(backslash, &mut prev_escaped);
+        let quote = quote & !escaped;
+        let in_string = prefix_xor(quote) ^ prev_in_string;
+        prev_in_string = ((in_string as i64) >> 63) as u64;
+        // Characters in strings and closing quotes.
+        let string_tail = in_string ^ quote;
+
+        let scalar = !(op | whitespace);
+        let nonquote_scalar = scalar & !quote;
+        let follows_nonquote_scalar = nonquote_scalar << 1 | prev_scalar;
+        prev_scalar = nonquote_scalar >> 63;
+
+        let mut structural = (op | (scalar & !follows_nonquote_scalar)) & !string_tail;
+        if block.len() < BLOCK_SIZE {
+            structural &= (1 << block.len()) - 1;
+        }
+
+        let block_offset = (block_idx * BLOCK_SIZE) as u32;
+        while structural != 0 {
+            positions.push(block_offset + structural.trailing_zeros());
+            structural &= structural - 1;
+        }
+    }
+    positions
+}
+
+/// Returns the mask of characters escaped by a backslash.
+/// prev_escaped is 1 when the first character of the block is escaped by the previous block.
+#[inline]
+fn find_escaped(backslash: u64, prev_escaped: &mut u64) -> u64 {
+    let backslash = backslash & !*prev_escaped;
+    let follows_escape = backslash << 1 | *prev_escaped;
+    // A run of backslashes escapes the next character when it has an odd length.
+    let odd_sequence_starts = backslash & !EVEN_BITS & !follows_escape;
+    let (sequences_starting_on_even_bits, overflow) = odd_sequence_starts.overflowing_add(backslash);
+    *prev_escaped = overflow as u64;
+    let invert_mask = sequences_starting_on_even_bits << 1;
+    (EVEN_BITS ^ invert_mask) & follows_escape
+}
+
+/// Each bit becomes the xor of itself and all lower bits, so bits between two quotes are set.
+#[inline]
+fn prefix_xor(mut mask: u64) -> u64 {
+    mask ^= mask << 1;
+    mask ^= mask << 2;
+    mask ^= mask << 4;
+    mask ^= mask << 8;
+    mask ^= mask << 16;
+    mask ^= mask << 32;
+    mask
+}
@@ -0,0 +1,179 @@
+/*!
+Record splitter for [JSON Lines](https://jsonlines.org/) (NDJSON).
+
+Lines are found with `Bufchr`, so splitting runs at the speed of the newline search.
+*/
+
+#![forbid(unsafe_code)]
+
+use core::fmt;
+
+use crate::bufchr::Bufchr;
+#[cfg(feature = "alloc")]
+use crate::json;
+
+/// One non blank line of a JSON Lines haystack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// 1-based line number.
+    pub line: usize,
+    /// Byte offset of the first byte of the line.
+    pub offset: usize,
+    /// The line without the line feed and a trailing carriage return.
+    pub data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Returns the value of key in the top level object of the record, as written: a string with its quotes,
+    /// a number, `true`, `false`, `null`, an object or an array. None is returned when the record is not an object,
+    /// is not closed or does not have key. Keys are compared with the bytes between their quotes, so escapes are not decoded.
+    ///
+    /// The structural characters of the record are found with `json::structural_index` on every call,
+    /// and only those of the top level are looked at. The record is not validated.
+    ///
+    /// ```
+    /// use bufchr::jsonl::Records;
+    ///
+    /// let haystack = br#"{"id": 7, "tags": ["a", {"id": 8}], "name": "x\",y" }"#;
+    /// let record = Records::new(haystack).next().unwrap().unwrap();
+    /// assert_eq!(record.get(b"id"), Some(&b"7"[..]));
+    /// assert_eq!(record.get(b"tags"), Some(&br#"["a", {"id": 8}]"#[..]));
+    /// assert_eq!(record.get(b"name"), Some(&br#""x\",y""#[..]));
+    /// assert_eq!(record.get(b"y"), None);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn get(&self, key: &[u8]) -> Option<&'a [u8]> {
+        let data = self.data;
+        let index = json::structural_index(data);
+        let mut positions = index.iter().map(|&pos| pos as usize);
+        if data[positions.next()?] != b'{' {
+            return None;
+        }
+        while let Some(name) = positions.next() {
+            // The name of a member, a colon and the start of its value.
+            let colon = positions.next()?;
+            let value = positions.next()?;
+            if data[name] != b'"' || data[colon] != b':' {
+                return None;
+            }
+            // The value ends at the comma or the brace after it at the top level.
+            let mut depth = matches!(data[value], b'{' | b'[') as usize;
+            let end = positions.find(|&pos| {
+                match data[pos] {
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' if depth > 0 => depth -= 1,
+                    b',' | b'}' if depth == 0 => return true,
+                    _ => {}
+                }
+                false
+            })?;
+            let name = trim_end(&data[name..colon]);
+            if name.len() == key.len() + 2 && &name[1..name.len() - 1] == key && name.ends_with(b"\"") {
+                return Some(trim_end(&data[value..end]));
+            }
+            if data[end] == b'}' {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+/// A line that does not start with `{` when validation is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordError {
+    /// 1-based line number.
+    pub line: usize,
+    /// Byte offset of the first byte of the line.
+    pub offset: usize,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} (byte {}): record does not start with '{{'", self.line, self.offset)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RecordError {}
+
+/// Iterator over the records of a JSON Lines haystack. Blank lines are skipped.
+///
+/// ```
+/// use bufchr::jsonl::Records;
+///
+/// let haystack = b"{\"a\":1}\n\n[2]\r\n{\"a\":3}";
+/// let mut records = Records::new(haystack).validate(true);
+/// assert_eq!(records.next().unwrap().unwrap().data, b"{\"a\":1}");
+/// assert_eq!(records.next().unwrap().unwrap_err().line, 3);
+/// assert_eq!(records.next().unwrap().unwrap().offset, 14);
+/// assert!(records.next().is_none());
+/// ```
+pub struct Records<'a> {
+    haystack: &'a [u8],
+    newlines: Bufchr<'a>,
+    position: usize,
+    line: usize,
+    validate: bool,
+}
+
+impl<'a> Records<'a> {
+    /// haystack is split on `\n`. Records are not validated by default.
+    pub fn new(haystack: &'a [u8]) -> Records<'a> {
+        Records {
+            haystack,
+            newlines: Bufchr::new(haystack, b'\n'),
+            position: 0,
+            line: 0,
+            validate: false,
+        }
+    }
+
+    /// When validate is true, a record whose first non whitespace byte is not `{` is returned as a `RecordError`.
+    pub fn validate(mut self, validate: bool) -> Records<'a> {
+        self.validate = validate;
+        self
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.position;
+            let end = match self.newlines.next() {
+                Some(end) => end,
+                None if start < self.haystack.len() => self.haystack.len(),
+                None => return None,
+            };
+            self.position = end + 1;
+            self.line += 1;
+
+            let mut data = &self.haystack[start..end];
+            if let Some((&b'\r', rest)) = data.split_last() {
+                data = rest;
+            }
+            let first = match data.iter().find(|&&b| !is_whitespace(b)) {
+                Some(&first) => first,
+                None => continue,
+            };
+            if self.validate && first != b'{' {
+                return Some(Err(RecordError { line: self.line, offset: start }));
+            }
+            return Some(Ok(Record { line: self.line, offset: start, data }));
+        }
+    }
+}
+
+#[inline]
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\r'
+}
+
+/// bytes without the whitespace at its end.
+#[cfg(feature = "alloc")]
+fn trim_end(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|&b| !is_whitespace(b) && b != b'\n').map_or(0, |i| i + 1);
+    &bytes[..len]
+}
@@ -23,19 +23,50 @@ assert_eq!(bf.next(), Some(3));
 assert_eq!(bf.next(), Some(7));
 assert_eq!(bf.next(), None);
 
+ # Example: count
+ When only the number of needles is needed, count does not create positions at all.
+
+
+let haystack = b"a1,b11,c111\na2,b22,c222\n";
+assert_eq!(bufchr::count(haystack, b','), 4);
+assert_eq!(bufchr::count2(haystack, b',', b'\n'), 6);
+
+ # Features
+ * `std` (default): cpu features are detected at runtime and `BUFCHR_FORCE_BACKEND` is read.
+   Without it the crate is `no_std` and the backend is chosen from the target features enabled at compile time,
+   e.g. `-C target-feature=+avx2`. The iterators, `BufchrCSV`, `csv::Fields` and `jsonl` only need `core`.
+ * `alloc` (enabled by `std`): `json::structural_index`, `csv::validate`, `csv::sniff`, `csv::to_utf8` and `csv::Fields::unescaped`, which use a `Vec`.
+ * `portable_simd` (nightly): a `core::simd` backend, `Backend::Portable`. It is the default on targets
+   without a hand written backend and can be selected with `with_backend` everywhere.
+
 
 
 */
 
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "portable_simd", feature(portable_simd))]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
 // #[doc(hidden)]
 // pub use crate::bufchr::{bufchr, bufchr2, bufchr3, CbBufchr, CbBufchr2, CbBufchr3};
 
 pub use crate::bufchr::{
     Bufchr, Bufchr2, Bufchr3, BufchrCSV,
+    BlockMasks, BlockMasks2, BlockMasks3,
+    count, count2, count3, count_csv, find_json_escape,
+    count_with_backend, count2_with_backend, count3_with_backend, count_csv_with_backend,
+    Backend, ParseBackendError, active_backend,
 };
 
 pub mod bufchr;
+pub mod csv;
+#[cfg(feature = "alloc")]
+pub mod json;
+pub mod jsonl;
 
+#[cfg(feature = "std")]
 #[doc(hidden)]
 pub fn test_bufchr() {
     println!("======== Start test_bufchr ========");
@@ -87,6 +118,7 @@ pub fn test_bufchr() {
     println!("======== End ========");
 }
 
+#[cfg(feature = "std")]
 #[doc(hidden)]
 pub fn test_bufchr2() {
     println!("======== Start test_bufchr2 ========");
@@ -147,6 +179,7 @@ pub fn test_bufchr2() {
     println!("======== End ========");
 }
 
+#[cfg(feature = "std")]
 #[doc(hidden)]
 pub fn test_bufchr3() {
     println!("======== Start test_bufchr2 ========");
@@ -216,11 +249,13 @@ pub fn test_bufchr3() {
     println!("======== End ========");
 }
 
+#[cfg(feature = "std")]
 #[repr(align(32))]
 struct AlignTo32{
     pub data:[u8;512]
 }
 
+#[cfg(feature = "std")]
 #[doc(hidden)]
 pub fn test_bufchrcsv() {
     println!("======== Start test_bufchrcsv ========");
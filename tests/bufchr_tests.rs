@@ -2,7 +2,7 @@
 #[cfg(test)]
 mod tests {
     use std::mem;
-    use bufchr::{Bufchr, Bufchr2, Bufchr3, BufchrCSV};
+    use bufchr::{Backend, Bufchr, Bufchr2, Bufchr3, BufchrCSV, BlockMasks, BlockMasks2, BlockMasks3};
     use super::*;
 
     // https://jack.wrenn.fyi/blog/include-transmute/
@@ -62,7 +62,7 @@ mod tests {
     fn test_0001_02_checkshort() {
         let haystack = b"a1,b11,c111,d1111,e11111";
         let needle = b',';
-        let mut bf = Bufchr::new_avx(haystack, needle);
+        let mut bf = Bufchr::with_backend(haystack, needle, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(6));
         assert_eq!(bf.next(), Some(11));
@@ -74,7 +74,7 @@ mod tests {
     fn test_0001_03_checkshort() {
         let haystack = b"a1,b11,c111,d1111,e11111";
         let needle = b',';
-        let mut bf = Bufchr::new_sse2(haystack, needle);
+        let mut bf = Bufchr::with_backend(haystack, needle, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(6));
         assert_eq!(bf.next(), Some(11));
@@ -101,7 +101,7 @@ mod tests {
         let haystack = b"a1,b11,c111,d1111,e11111\n";
         let n1 = b',';
         let n2 = b'\n';
-        let mut bf = Bufchr2::new_avx(haystack, n1, n2);
+        let mut bf = Bufchr2::with_backend(haystack, n1, n2, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(6));
         assert_eq!(bf.next(), Some(11));
@@ -115,7 +115,7 @@ mod tests {
         let haystack = b"a1,b11,c111,d1111,e11111\n";
         let n1 = b',';
         let n2 = b'\n';
-        let mut bf = Bufchr2::new_sse2(haystack, n1, n2);
+        let mut bf = Bufchr2::with_backend(haystack, n1, n2, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(6));
         assert_eq!(bf.next(), Some(11));
@@ -147,7 +147,7 @@ mod tests {
         let n1 = b',';
         let n2 = b'\n';
         let n3 = b'"';
-        let mut bf = Bufchr3::new_avx(haystack, n1, n2, n3);
+        let mut bf = Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(3));
         assert_eq!(bf.next(), Some(7));
@@ -164,7 +164,7 @@ mod tests {
         let n1 = b',';
         let n2 = b'\n';
         let n3 = b'"';
-        let mut bf = Bufchr3::new_sse2(haystack, n1, n2, n3);
+        let mut bf = Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(3));
         assert_eq!(bf.next(), Some(7));
@@ -216,7 +216,7 @@ mod tests {
         let n1 = b',';
         let n2 = b'\n';
         let n3 = b'"';
-        let mut bf = BufchrCSV::new_sse2(&a32.data,  b',');
+        let mut bf = BufchrCSV::with_backend(&a32.data,  b',', Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(3));
         assert_eq!(bf.next(), Some(7));
@@ -242,7 +242,7 @@ mod tests {
         let n1 = b',';
         let n2 = b'\n';
         let n3 = b'"';
-        let mut bf = BufchrCSV::new_avx(&a32.data,  b',');
+        let mut bf = BufchrCSV::with_backend(&a32.data,  b',', Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(2));
         assert_eq!(bf.next(), Some(3));
         assert_eq!(bf.next(), Some(7));
@@ -298,20 +298,20 @@ mod tests {
     fn test_0004_02_no_needle_in_first_vector() {
         let haystack = b"0123456789012345678901234567890123,567,8\"0\n123456789012345678901234567890";
         let n1 = b',';
-        let mut bf = Bufchr::new_avx(haystack, n1);
+        let mut bf = Bufchr::with_backend(haystack, n1, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), None);
 
         let n2 = b'"';
-        let mut bf = Bufchr2::new_avx(haystack, n1, n2);
+        let mut bf = Bufchr2::with_backend(haystack, n1, n2, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), Some(40));
         assert_eq!(bf.next(), None);
 
         let n3 = b'\n';
-        let mut bf = Bufchr3::new_avx(haystack, n1, n2, n3);
+        let mut bf = Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), Some(40));
@@ -327,7 +327,7 @@ mod tests {
         for u in haystack.iter(){
             a32.data[idx] = *u;idx +=1;
         }
-        let mut bf = BufchrCSV::new_avx(&a32.data, b',');
+        let mut bf = BufchrCSV::with_backend(&a32.data, b',', Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(64 + 34));
         assert_eq!(bf.next(), Some(64 + 38));
         assert_eq!(bf.next(), Some(64 + 40));
@@ -339,20 +339,20 @@ mod tests {
     fn test_0004_03_no_needle_in_first_vector() {
         let haystack = b"0123456789012345678901234567890123,567,8\"0\n123456789012345678901234567890";
         let n1 = b',';
-        let mut bf = Bufchr::new_sse2(haystack, n1);
+        let mut bf = Bufchr::with_backend(haystack, n1, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), None);
 
         let n2 = b'"';
-        let mut bf = Bufchr2::new_sse2(haystack, n1, n2);
+        let mut bf = Bufchr2::with_backend(haystack, n1, n2, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), Some(40));
         assert_eq!(bf.next(), None);
 
         let n3 = b'\n';
-        let mut bf = Bufchr3::new_sse2(haystack, n1, n2, n3);
+        let mut bf = Bufchr3::with_backend(haystack, n1, n2, n3, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), Some(40));
@@ -369,7 +369,7 @@ mod tests {
         for u in haystack.iter(){
             a32.data[idx] = *u;idx +=1;
         }
-        let mut bf = BufchrCSV::new_sse2(&a32.data,  b',');
+        let mut bf = BufchrCSV::with_backend(&a32.data,  b',', Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(64 + 34));
         assert_eq!(bf.next(), Some(64 + 38));
         assert_eq!(bf.next(), Some(64 + 40));
@@ -382,7 +382,7 @@ mod tests {
     fn test_0004_04_no_needle_in_first_vector() {
         let haystack = b"0123456789012345678901234567890123,567,8\"0\n123456789012345678901234567890";
         let n1 = b',';
-        let mut bf = Bufchr::new_sse2(haystack, n1);
+        let mut bf = Bufchr::with_backend(haystack, n1, Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(34));
         assert_eq!(bf.next(), Some(38));
         assert_eq!(bf.next(), None);
@@ -414,7 +414,7 @@ mod tests {
         for u in haystack.iter(){
             a32.data[idx] = *u;idx +=1;
         }
-        let mut bf = BufchrCSV::new_sse2(&a32.data,  b',');
+        let mut bf = BufchrCSV::with_backend(&a32.data,  b',', Backend::Sse2).unwrap();
         assert_eq!(bf.next(), Some(128 + 34));
         assert_eq!(bf.next(), Some(128 + 38));
         assert_eq!(bf.next(), Some(128 + 40));
@@ -513,11 +513,761 @@ mod tests {
         assert_eq!(bf.next(), None);
     }
 
+    #[test]
+    fn test_0005_01_count_short() {
+        let haystack = b"a1,\"b11\",c111,d1111,e11111\n";
+        assert_eq!(bufchr::count(haystack, b','), 4);
+        assert_eq!(bufchr::count2(haystack, b',', b'\n'), 5);
+        assert_eq!(bufchr::count3(haystack, b',', b'\n', b'"'), 7);
+        assert_eq!(bufchr::count_csv(haystack, b','), 7);
+        assert_eq!(bufchr::count(b"", b','), 0);
+        assert_eq!(bufchr::count(b"abc", b','), 0);
+    }
+
+    #[test]
+    fn test_0005_02_count_only_needles() {
+        // more than 255 vectors of needles, so every byte counter is flushed before it overflows.
+        let haystack = vec![b','; 32 * 300 + 7];
+        assert_eq!(bufchr::count(&haystack, b','), haystack.len());
+        assert_eq!(bufchr::count2(&haystack, b',', b'\n'), haystack.len());
+        assert_eq!(bufchr::count3(&haystack, b',', b'\n', b'"'), haystack.len());
+        assert_eq!(bufchr::count_csv(&haystack, b','), haystack.len());
+        unsafe {
+            if is_x86_feature_detected!("avx2") {
+                assert_eq!(bufchr::bufchr::avx::count(&haystack, b','), haystack.len());
+            }
+            assert_eq!(bufchr::bufchr::sse2::count(&haystack, b','), haystack.len());
+        }
+    }
+
+    #[test]
+    fn test_0005_03_count_every_length() {
+        let line = b"a1,\"b11\",c111,d1111,e11111\n";
+        let haystack: Vec<u8> = line.iter().cycle().take(line.len() * 40).cloned().collect();
+        for len in 0..haystack.len() {
+            let haystack = &haystack[..len];
+            let expected = haystack.iter().filter(|&&b| b == b',' || b == b'\n' || b == b'"').count();
+            assert_eq!(bufchr::count3(haystack, b',', b'\n', b'"'), expected);
+            assert_eq!(bufchr::count_csv(haystack, b','), expected);
+            unsafe {
+                if is_x86_feature_detected!("avx2") {
+                    assert_eq!(bufchr::bufchr::avx::count3(haystack, b',', b'\n', b'"'), expected);
+                    assert_eq!(bufchr::bufchr::avx::count_csv(haystack, b','), expected);
+                }
+                assert_eq!(bufchr::bufchr::sse2::count3(haystack, b',', b'\n', b'"'), expected);
+                assert_eq!(bufchr::bufchr::sse2::count_csv(haystack, b','), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_0006_01_fill_positions() {
+        let haystack = b"a1,\"b11\",c111,d1111,e11111\n";
+        let mut out = [0u32; 16];
+        let mut bf = Bufchr3::new(haystack, b',', b'\n', b'"');
+        assert_eq!(bf.fill_positions(&mut out), 7);
+        assert_eq!(&out[..7], &[2, 3, 7, 8, 13, 19, 26]);
+        assert_eq!(bf.fill_positions(&mut out), 0);
+
+        let mut bf = Bufchr::new(haystack, b',');
+        assert_eq!(bf.fill_positions(&mut out[..2]), 2);
+        assert_eq!(&out[..2], &[2, 8]);
+        assert_eq!(bf.next(), Some(13));
+        assert_eq!(bf.fill_positions(&mut out), 1);
+        assert_eq!(out[0], 19);
+        assert_eq!(bf.next(), None);
+    }
+
+    #[test]
+    fn test_0006_02_fill_positions_same_as_next() {
+        for out_len in [1, 3, 4, 7, 64, 100].iter() {
+            let mut out = vec![0u32; *out_len];
+
+            let mut positions = Vec::new();
+            let mut bf = Bufchr::new(HAYSTACK_ISO_3166, b',');
+            loop {
+                let written = bf.fill_positions(&mut out);
+                if written == 0 { break; }
+                positions.extend(out[..written].iter().map(|&p| p as usize));
+            }
+            assert_eq!(positions, Bufchr::new(HAYSTACK_ISO_3166, b',').collect::<Vec<_>>());
+
+            let mut positions = Vec::new();
+            let mut bf = Bufchr2::new(HAYSTACK_ISO_3166, b',', b'\n');
+            loop {
+                let written = bf.fill_positions(&mut out);
+                if written == 0 { break; }
+                positions.extend(out[..written].iter().map(|&p| p as usize));
+            }
+            assert_eq!(positions, Bufchr2::new(HAYSTACK_ISO_3166, b',', b'\n').collect::<Vec<_>>());
+
+            let mut positions = Vec::new();
+            let mut bf = Bufchr3::new(HAYSTACK_ISO_3166, b',', b' ', b'\n');
+            loop {
+                let written = bf.fill_positions(&mut out);
+                if written == 0 { break; }
+                positions.extend(out[..written].iter().map(|&p| p as usize));
+            }
+            assert_eq!(positions, Bufchr3::new(HAYSTACK_ISO_3166, b',', b' ', b'\n').collect::<Vec<_>>());
+
+            let mut positions = Vec::new();
+            let mut bf = BufchrCSV::new(HAYSTACK_ISO_3166, b',');
+            loop {
+                let written = bf.fill_positions(&mut out);
+                if written == 0 { break; }
+                positions.extend(out[..written].iter().map(|&p| p as usize));
+            }
+            assert_eq!(positions, BufchrCSV::new(HAYSTACK_ISO_3166, b',').collect::<Vec<_>>());
+        }
+    }
+
+    fn naive_mask(block: &[u8], needle: u8) -> u64 {
+        block.iter().take(64).enumerate()
+            .filter(|(_, &b)| b == needle)
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    #[test]
+    fn test_0007_01_block_masks() {
+        let haystack = b"0123456789012345678901234567890123,567,8\"0\n123456789012345678901234567890";
+        let mut bm = BlockMasks::new(haystack, b',');
+        assert_eq!(bm.next(), Some((0, 1 << 34 | 1 << 38)));
+        assert_eq!(bm.next(), Some((64, 0)));
+        assert_eq!(bm.next(), None);
+
+        let mut bm = BlockMasks3::new(haystack, b',', b'"', b'\n');
+        assert_eq!(bm.next(), Some((0, [1 << 34 | 1 << 38, 1 << 40, 1 << 42])));
+        assert_eq!(bm.next(), Some((64, [0, 0, 0])));
+        assert_eq!(bm.next(), None);
+
+        assert_eq!(BlockMasks::new(b"", b',').next(), None);
+    }
+
+    #[test]
+    fn test_0007_02_block_masks_iso_3166() {
+        let blocks = HAYSTACK_ISO_3166.chunks(64);
+        let expected: Vec<_> = blocks.enumerate()
+            .map(|(i, block)| (i * 64, naive_mask(block, b','))).collect();
+        assert_eq!(BlockMasks::new(HAYSTACK_ISO_3166, b',').collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks::with_backend(HAYSTACK_ISO_3166, b',', Backend::Avx2).unwrap().collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks::with_backend(HAYSTACK_ISO_3166, b',', Backend::Sse2).unwrap().collect::<Vec<_>>(), expected);
+
+        let expected: Vec<_> = HAYSTACK_ISO_3166.chunks(64).enumerate()
+            .map(|(i, block)| (i * 64, [naive_mask(block, b','), naive_mask(block, b'\n')])).collect();
+        assert_eq!(BlockMasks2::new(HAYSTACK_ISO_3166, b',', b'\n').collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks2::with_backend(HAYSTACK_ISO_3166, b',', b'\n', Backend::Avx2).unwrap().collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks2::with_backend(HAYSTACK_ISO_3166, b',', b'\n', Backend::Sse2).unwrap().collect::<Vec<_>>(), expected);
+
+        let expected: Vec<_> = HAYSTACK_ISO_3166.chunks(64).enumerate()
+            .map(|(i, block)| (i * 64,
+                [naive_mask(block, b','), naive_mask(block, b' '), naive_mask(block, b'\n')])).collect();
+        assert_eq!(BlockMasks3::new(HAYSTACK_ISO_3166, b',', b' ', b'\n').collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks3::with_backend(HAYSTACK_ISO_3166, b',', b' ', b'\n', Backend::Avx2).unwrap().collect::<Vec<_>>(), expected);
+        assert_eq!(BlockMasks3::with_backend(HAYSTACK_ISO_3166, b',', b' ', b'\n', Backend::Sse2).unwrap().collect::<Vec<_>>(), expected);
+    }
+
+    // Byte by byte version of bufchr::json::structural_index.
+    fn naive_structural_index(haystack: &[u8]) -> Vec<u32> {
+        let is_op = |b: u8| b"{}[]:,".contains(&b);
+        let is_whitespace = |b: u8| b" \t\n\r".contains(&b);
+        let mut positions = Vec::new();
+        let mut escaped = false;
+        let mut in_string = false;
+        let mut prev_nonquote_scalar = false;
+        for (i, &b) in haystack.iter().enumerate() {
+            let quote = b == b'"' && !escaped;
+            escaped = b == b'\\' && !escaped;
+            let string_tail = in_string;
+            if quote {
+                in_string = !in_string;
+            }
+            let scalar = !is_op(b) && !is_whitespace(b);
+            if !string_tail && (is_op(b) || (scalar && !prev_nonquote_scalar)) {
+                positions.push(i as u32);
+            }
+            prev_nonquote_scalar = scalar && !quote;
+        }
+        positions
+    }
+
+    #[test]
+    fn test_0008_01_json_structural_index() {
+        let haystack = br#"{"a": [1, "x,y"]}"#;
+        assert_eq!(bufchr::json::structural_index(haystack), vec![0, 1, 4, 6, 7, 8, 10, 15, 16]);
+        let haystack = br#"{"k\"e\\y": true, "v": [null,-1.5e3]}"#;
+        assert_eq!(bufchr::json::structural_index(haystack), naive_structural_index(haystack));
+        assert_eq!(bufchr::json::structural_index(b""), vec![]);
+    }
+
+    #[test]
+    fn test_0008_02_json_structural_index_across_blocks() {
+        // strings and backslash runs of every length cross the 64 byte block boundaries.
+        let mut haystack = Vec::new();
+        for i in 0..200 {
+            haystack.extend_from_slice(b"{\"key\": \"");
+            haystack.extend(std::iter::repeat_n(b'\\', i % 7));
+            haystack.extend(std::iter::repeat_n(b'x', i % 13));
+            haystack.extend_from_slice(b"\", \"n\": [1, 22, true, \"{[,]}\"]}\n");
+        }
+        for len in (0..haystack.len()).step_by(7) {
+            let haystack = &haystack[..len];
+            assert_eq!(bufchr::json::structural_index(haystack), naive_structural_index(haystack));
+        }
+    }
+
+    #[test]
+    fn test_0008_03_json_block_every_byte() {
+        let haystack: Vec<u8> = (0..=255).collect();
+        for block in haystack.chunks(64) {
+            let expected = bufchr::bufchr::fallback::json_block(block);
+            unsafe {
+                if is_x86_feature_detected!("avx2") {
+                    assert_eq!(bufchr::bufchr::avx::json_block(block), expected);
+                }
+                assert_eq!(bufchr::bufchr::sse2::json_block(block), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_0009_01_jsonl_records() {
+        use bufchr::jsonl::{Record, RecordError, Records};
+        let haystack = b"{\"a\":1}\r\n  \n\n{\"a\":2}\nnull\n\t{\"a\":3}";
+        let records: Vec<_> = Records::new(haystack).collect();
+        assert_eq!(records, vec![
+            Ok(Record { line: 1, offset: 0, data: b"{\"a\":1}" }),
+            Ok(Record { line: 4, offset: 13, data: b"{\"a\":2}" }),
+            Ok(Record { line: 5, offset: 21, data: b"null" }),
+            Ok(Record { line: 6, offset: 26, data: b"\t{\"a\":3}" }),
+        ]);
+
+        let records: Vec<_> = Records::new(haystack).validate(true).collect();
+        assert_eq!(records[2], Err(RecordError { line: 5, offset: 21 }));
+        assert_eq!(records[3].unwrap().line, 6);
+        assert_eq!(records[2].unwrap_err().to_string(), "line 5 (byte 21): record does not start with '{'");
+
+        assert_eq!(Records::new(b"").next(), None);
+        assert_eq!(Records::new(b"\n\n").next(), None);
+    }
+
+    #[test]
+    fn test_0009_02_jsonl_records_long() {
+        let mut haystack = Vec::new();
+        for i in 0..1000 {
+            haystack.extend_from_slice(format!("{{\"id\":{},\"pad\":\"{}\"}}\n", i, "x".repeat(i % 97)).as_bytes());
+        }
+        let records: Vec<_> = bufchr::jsonl::Records::new(&haystack).validate(true)
+            .map(|record| record.unwrap()).collect();
+        assert_eq!(records.len(), 1000);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.line, i + 1);
+            assert_eq!(&haystack[record.offset..record.offset + record.data.len()], record.data);
+            assert!(record.data.starts_with(format!("{{\"id\":{},", i).as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_0009_03_jsonl_record_get() {
+        use bufchr::jsonl::Records;
+        let haystack = br#" { "a" : { "a": 1, "b": [2, "}"] } , "b\"c":"{,}", "d":[] ,"a":null, "e" : -1.5e3 }"#;
+        let record = Records::new(haystack).next().unwrap().unwrap();
+        // The first member with the key, and not the ones of nested objects.
+        assert_eq!(record.get(b"a"), Some(&br#"{ "a": 1, "b": [2, "}"] }"#[..]));
+        assert_eq!(record.get(b"b"), None);
+        assert_eq!(record.get(br#"b\"c"#), Some(&br#""{,}""#[..]));
+        assert_eq!(record.get(b"d"), Some(&b"[]"[..]));
+        assert_eq!(record.get(b"e"), Some(&b"-1.5e3"[..]));
+        assert_eq!(record.get(b"1"), None);
+
+        // Not an object, or one that is empty, broken or not closed.
+        for haystack in [&b"{}"[..], b"[{\"a\": 1}]", b"null", b"\"a\"", b"{\"a\"}", b"{\"a\": 1"] {
+            let record = Records::new(haystack).next().unwrap().unwrap();
+            assert_eq!(record.get(b"a"), None, "{:?}", record);
+        }
+
+        // Members in later blocks than the opening brace.
+        let haystack = format!("{{\"pad\":\"{}\",\"id\":{{\"n\":[{}]}}}}", "x".repeat(100), "7,".repeat(40) + "7");
+        let record = Records::new(haystack.as_bytes()).next().unwrap().unwrap();
+        assert_eq!(record.get(b"id").unwrap(), format!("{{\"n\":[{}]}}", "7,".repeat(40) + "7").as_bytes());
+    }
+
+    // Fails to compile if an iterator stops being Send + Sync.
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_0010_01_send_sync() {
+        assert_send_sync::<Bufchr<'static>>();
+        assert_send_sync::<Bufchr2<'static>>();
+        assert_send_sync::<Bufchr3<'static>>();
+        assert_send_sync::<BufchrCSV<'static>>();
+        assert_send_sync::<BlockMasks<'static>>();
+        assert_send_sync::<BlockMasks2<'static>>();
+        assert_send_sync::<BlockMasks3<'static>>();
+        assert_send_sync::<bufchr::jsonl::Records<'static>>();
+    }
+
+    #[test]
+    fn test_0010_02_iterator_in_thread() {
+        let mut bf = Bufchr::new(HAYSTACK_ISO_3166, b'\n');
+        let first = bf.next();
+        let rest = std::thread::spawn(move || bf.count()).join().unwrap();
+        assert_eq!(first.is_some() as usize + rest, bufchr::count(HAYSTACK_ISO_3166, b'\n'));
+    }
+
+    #[test]
+    fn test_0011_01_backend_constructors() {
+        let haystack = b"a1,b11,c111,d1111,e11111";
+        assert_eq!(Bufchr::with_backend(haystack, b',', Backend::Avx2).is_some(), is_x86_feature_detected!("avx2"));
+        assert_eq!(BufchrCSV::with_backend(haystack, b',', Backend::Avx2).is_some(), is_x86_feature_detected!("avx2"));
+        assert_eq!(BlockMasks3::with_backend(haystack, b',', b'1', b'c', Backend::Avx2).is_some(), is_x86_feature_detected!("avx2"));
+        assert_eq!(Bufchr3::with_backend(haystack, b',', b'1', b'c', Backend::Sse2).is_some(), is_x86_feature_detected!("sse2"));
+        assert!(Bufchr2::with_backend(haystack, b',', b'1', Backend::Scalar).is_some());
+        assert!(Bufchr::with_backend(haystack, b',', Backend::Avx512).is_none());
+        assert!(BlockMasks::with_backend(haystack, b',', Backend::Neon).is_none());
+
+        let positions: Vec<usize> = Bufchr3::with_backend(haystack, b',', b'1', b'c', Backend::Scalar).unwrap().collect();
+        assert_eq!(positions, Bufchr3::new(haystack, b',', b'1', b'c').collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_0011_02_backend_functions() {
+        use bufchr::csv::{self, CsvDialect, Fields};
+        let haystack = b"a1,\"b\"\"1\",c111\nd1111,e11111\r\n\"f\n";
+        let dialect = CsvDialect::default();
+        for backend in [Backend::Avx2, Backend::Sse2, Backend::Scalar] {
+            if !backend.is_available() {
+                assert_eq!(bufchr::count_with_backend(haystack, b',', backend), None);
+                assert!(Fields::with_backend(haystack, dialect, backend).is_none());
+                assert_eq!(csv::validate_with_backend(haystack, dialect, backend), None);
+                continue;
+            }
+            assert_eq!(bufchr::count_with_backend(haystack, b'1', backend), Some(bufchr::count(haystack, b'1')));
+            assert_eq!(bufchr::count2_with_backend(haystack, b'1', b',', backend), Some(bufchr::count2(haystack, b'1', b',')));
+            assert_eq!(bufchr::count3_with_backend(haystack, b'1', b',', b'"', backend), Some(bufchr::count3(haystack, b'1', b',', b'"')));
+            assert_eq!(bufchr::count_csv_with_backend(haystack, b',', backend), Some(bufchr::count_csv(haystack, b',')));
+            let expected: Vec<_> = Fields::with_dialect(haystack, dialect).map(|f| (f.offset, f.data)).collect();
+            let fields: Vec<_> = Fields::with_backend(haystack, dialect, backend).unwrap().map(|f| (f.offset, f.data)).collect();
+            assert_eq!(fields, expected);
+            assert_eq!(csv::validate_with_backend(haystack, dialect, backend), Some(csv::validate(haystack, dialect)));
+        }
+        assert_eq!(bufchr::count_with_backend(haystack, b',', Backend::Neon), None);
+    }
+
+    #[test]
+    fn test_0013_01_active_backend() {
+        let backend = bufchr::active_backend();
+        assert!(backend.is_available());
+        if std::env::var(bufchr::bufchr::FORCE_BACKEND_ENV).is_err() && is_x86_feature_detected!("avx2") {
+            assert_eq!(backend, Backend::Avx2);
+        }
+        assert_eq!("AVX2".parse::<Backend>(), Ok(Backend::Avx2));
+        assert_eq!(" scalar ".parse::<Backend>(), Ok(Backend::Scalar));
+        assert_eq!(Backend::Sse2.to_string().parse::<Backend>(), Ok(Backend::Sse2));
+        assert!("mmx".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn test_0013_02_force_backend_env() {
+        // With avx2 enabled at compile time the kernels are called directly and the override is ignored.
+        if cfg!(target_feature = "avx2") {
+            return;
+        }
+        // The backend is selected once per process, so the override is checked in a child process.
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::test_0013_03_forced_scalar", "--ignored"])
+            .env(bufchr::bufchr::FORCE_BACKEND_ENV, "scalar")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("1 passed"));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_0013_03_forced_scalar() {
+        if std::env::var(bufchr::bufchr::FORCE_BACKEND_ENV).is_err() {
+            return;
+        }
+        assert_eq!(bufchr::active_backend(), Backend::Scalar);
+        assert_eq!(Bufchr::new(b"a1,b11", b',').collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_0014_01_default_kernels() {
+        let bf: Bufchr<'_, bufchr::bufchr::DefaultKernels> = Bufchr::new(b"a1,b11,c111", b',');
+        assert_eq!(bf.collect::<Vec<_>>(), vec![2, 6]);
+        let bf: BufchrCSV<'_, bufchr::bufchr::DynamicKernels> = BufchrCSV::with_backend(b"a1,\"b\"\n", b',', Backend::Scalar).unwrap();
+        assert_eq!(bf.collect::<Vec<_>>(), vec![2, 3, 5, 6]);
+        if cfg!(target_feature = "avx2") {
+            assert_eq!(bufchr::active_backend(), Backend::Avx2);
+        }
+    }
+
+    #[test]
+    fn test_0015_01_portable_backend() {
+        assert_eq!(Backend::Portable.is_available(), cfg!(feature = "portable_simd"));
+        assert_eq!("portable".parse::<Backend>(), Ok(Backend::Portable));
+        let haystack = HAYSTACK_ISO_3166;
+        let bf = match Bufchr::with_backend(haystack, b',', Backend::Portable) {
+            Some(bf) => bf,
+            None => return,
+        };
+        assert_eq!(bf.collect::<Vec<_>>(), Bufchr::new(haystack, b',').collect::<Vec<_>>());
+        assert_eq!(Bufchr3::with_backend(haystack, b',', b' ', b'\n', Backend::Portable).unwrap().collect::<Vec<_>>(),
+            Bufchr3::new(haystack, b',', b' ', b'\n').collect::<Vec<_>>());
+        assert_eq!(BufchrCSV::with_backend(haystack, b',', Backend::Portable).unwrap().collect::<Vec<_>>(),
+            BufchrCSV::new(haystack, b',').collect::<Vec<_>>());
+        assert_eq!(BlockMasks2::with_backend(haystack, b',', b'\n', Backend::Portable).unwrap().collect::<Vec<_>>(),
+            BlockMasks2::new(haystack, b',', b'\n').collect::<Vec<_>>());
+    }
+
+    fn csv_fields(haystack: &[u8]) -> Vec<(String, bool, bool)> {
+        bufchr::csv::Fields::new(haystack, b',')
+            .map(|f| (String::from_utf8(f.data.to_vec()).unwrap(), f.quoted, f.last))
+            .collect()
+    }
+
+    #[test]
+    fn test_0016_01_csv_fields() {
+        let s = |data: &str, quoted: bool, last: bool| (data.to_string(), quoted, last);
+        assert_eq!(csv_fields(b""), vec![]);
+        assert_eq!(csv_fields(b"\n\r\n"), vec![]);
+        assert_eq!(csv_fields(b"a"), vec![s("a", false, true)]);
+        assert_eq!(csv_fields(b"a,"), vec![s("a", false, false), s("", false, true)]);
+        assert_eq!(csv_fields(b",\n"), vec![s("", false, false), s("", false, true)]);
+        assert_eq!(csv_fields(b"\"\"\r\n"), vec![s("", true, true)]);
+        assert_eq!(csv_fields(b"a,\"b\nc\",\"d\"\"\"\r\n\ne\n"),
+            vec![s("a", false, false), s("b\nc", true, false), s("d\"\"", true, true), s("e", false, true)]);
+        // A quote inside an unquoted field is data, and an unclosed quote runs to the end.
+        assert_eq!(csv_fields(b"a\"b,\"c\n"), vec![s("a\"b", false, false), s("\"c\n", false, true)]);
+        let offsets: Vec<usize> = bufchr::csv::Fields::new(b"ab;\"c\"\n\nd", b';').map(|f| f.offset).collect();
+        assert_eq!(offsets, vec![0, 3, 8]);
+
+        // position() is where the next record starts, or past the end for a record without a line feed.
+        let mut fields = bufchr::csv::Fields::new(b"a,b\r\nc", b',');
+        fields.next();
+        assert_eq!(fields.position(), 2);
+        fields.next();
+        assert_eq!(fields.position(), 5);
+        fields.next();
+        assert_eq!(fields.position(), 7);
+    }
+
+    #[test]
+    fn test_0016_02_csv_fields_iso_3166() {
+        // Same records as splitting the lines, because no field of the fixture has a line feed.
+        let fields: Vec<_> = bufchr::csv::Fields::new(HAYSTACK_ISO_3166, b',').collect();
+        let records = fields.iter().filter(|f| f.last).count();
+        let lines = HAYSTACK_ISO_3166.split(|&b| b == b'\n').filter(|l| !l.is_empty() && *l != b"\r").count();
+        assert_eq!(records, lines);
+        assert!(fields.iter().all(|f| !f.data.ends_with(b"\r") && !f.data.contains(&b'\n')));
+    }
+
+    #[test]
+    fn test_0017_01_find_json_escape() {
+        // Bytes just outside the escaped ranges, including those that are negative as signed bytes.
+        let plain = [b' ', b'a', b'!', b'#', b'[', b']', 0x7F, 0x80, 0xE0, 0xFF];
+        for len in 0..200 {
+            let haystack: Vec<u8> = plain.iter().cycle().take(len).copied().collect();
+            assert_eq!(bufchr::find_json_escape(&haystack), None, "len: {}", len);
+            for pos in 0..len {
+                for &escape in &[b'"', b'\\', 0x00, b'\n', 0x1F] {
+                    let mut haystack = haystack.clone();
+                    haystack[pos] = escape;
+                    if pos + 1 < len {
+                        haystack[len - 1] = b'"';
+                    }
+                    assert_eq!(bufchr::find_json_escape(&haystack), Some(pos), "len: {} pos: {} byte: {}", len, pos, escape);
+                }
+            }
+        }
+    }
+
+    fn csv_errors(haystack: &[u8], delimiter: u8) -> Vec<(bufchr::csv::CsvErrorKind, usize, usize, usize, usize)> {
+        bufchr::csv::validate(haystack, bufchr::csv::CsvDialect::new(delimiter)).iter()
+            .map(|e| (e.kind, e.record, e.line, e.column, e.offset))
+            .collect()
+    }
+
+    #[test]
+    fn test_0018_01_csv_validate() {
+        use bufchr::csv::CsvErrorKind::*;
+        assert_eq!(csv_errors(b"", b','), vec![]);
+        assert_eq!(csv_errors(b"a,b\r\n\n\r\n\"x\"\"\ny\",\"\"\r\n1,2", b','), vec![]);
+        // The line and offset of a record after a quoted line feed.
+        assert_eq!(csv_errors(b"a;b\n\"x\ny\";z\"\n", b';'), vec![(StrayQuote, 2, 3, 2, 11)]);
+        // Only the first quote problem of a field is reported.
+        assert_eq!(csv_errors(b"\"a\"b\"c\",d\ne\"\"f,g\n", b','), vec![(TextAfterQuote, 1, 1, 1, 3), (StrayQuote, 2, 2, 1, 11)]);
+        // An unclosed quote ends the checks, and is reported where it opens.
+        assert_eq!(csv_errors(b"a,b\n1,\"2\n3,4\r5\n", b','), vec![(UnclosedQuote, 2, 2, 2, 6)]);
+        // Too few fields point at the line end, too many at the first extra field.
+        assert_eq!(csv_errors(b"a,b,c\n1,2\n1,2,3,4,5\n6", b','), vec![
+            (FieldCount { expected: 3, found: 2 }, 2, 2, 3, 9),
+            (FieldCount { expected: 3, found: 5 }, 3, 3, 4, 16),
+            (FieldCount { expected: 3, found: 1 }, 4, 4, 2, 21),
+        ]);
+        // A carriage return is fine in quotes or before a line feed. A lone one at the end is a blank line, not a record.
+        assert_eq!(csv_errors(b"a\rb,\"c\rd\"\r\n\r\n\r", b','), vec![
+            (BareCarriageReturn, 1, 1, 1, 1),
+            (BareCarriageReturn, 2, 3, 1, 13),
+        ]);
+        let error = bufchr::csv::validate(b"a\n\"b", Default::default())[0];
+        assert_eq!(error.to_string(), "record 2, line 2, column 1 (byte 2): quoted field is not closed");
+    }
+
+    #[test]
+    fn test_0018_02_csv_validate_iso_3166() {
+        assert_eq!(csv_errors(HAYSTACK_ISO_3166, b','), vec![]);
+        // Same records as Fields, so breaking one field changes only that record.
+        let mut haystack = HAYSTACK_ISO_3166.to_vec();
+        let offset = haystack.windows(7).position(|w| w == b"Andorra").unwrap();
+        haystack[offset + 3] = b',';
+        assert_eq!(csv_errors(&haystack, b','), vec![(bufchr::csv::CsvErrorKind::FieldCount { expected: 2, found: 3 }, 2, 2, 3, offset + 4)]);
+    }
+
+    #[test]
+    fn test_0019_01_csv_sniff() {
+        use bufchr::csv::{sniff, CsvDialect, Terminator};
+        assert_eq!(sniff(b""), CsvDialect::new(b','));
+        assert_eq!(sniff(b"no delimiters\nat all\n"), CsvDialect::new(b','));
+        assert_eq!(sniff(HAYSTACK_ISO_3166), CsvDialect::new(b',').terminator(Terminator::CrLf).header(true));
+        // Quoted delimiters and a record cut by the end of the sample do not count.
+        assert_eq!(sniff(b"1|\"a|b\"|2.5\n2|c|3\n3|\"d|e|f\"|4.25\n4|g"), CsvDialect::new(b'|'));
+        assert_eq!(sniff(b"id\tname\n1\tx\n2\ty,z\n3\tw\n"), CsvDialect::new(b'\t').header(true));
+        // An apostrophe inside a field is not a quote.
+        assert_eq!(sniff(b"a;b\rdon't;'x;y'\r'it''s';'z'\r").quote, b'\'');
+        assert_eq!(sniff(b"a;b\rdon't;'x;y'\r'it''s';'z'\r").terminator, Terminator::Cr);
+        assert_eq!(sniff(b"name,age\nO'Brien,32\nO'Neil,41\n"), CsvDialect::new(b',').header(true));
+        // A year above decimals is a header, an integer above integers is not.
+        assert!(sniff(b"country,1960,1961\nA,1.5,2.25\nB,3e9,\n").header);
+        assert!(!sniff(b"1,2\n3,4\n5,6\n").header);
+    }
+
+    #[test]
+    fn test_0019_02_csv_dialect() {
+        use bufchr::csv::{validate, CsvDialect, CsvErrorKind, Fields, Terminator};
+        let dialect = CsvDialect::new(b';').quote(b'\'').terminator(Terminator::Cr);
+        let haystack: Vec<u8> = HAYSTACK_ISO_3166.iter()
+            .map(|&b| match b { b',' => b';', b'"' => b'\'', b'\'' => b'`', b'\n' => b'\r', b => b })
+            .collect();
+        let expected: Vec<usize> = (0..haystack.len()).filter(|&i| b";\r'".contains(&haystack[i])).collect();
+        assert_eq!(BufchrCSV::with_dialect(&haystack, dialect).collect::<Vec<_>>(), expected);
+
+        let fields: Vec<(&[u8], bool, bool)> = Fields::with_dialect(b"a;'b;''c'\r\r'd\re'", dialect)
+            .map(|f| (f.data, f.quoted, f.last))
+            .collect();
+        assert_eq!(fields, vec![(&b"a"[..], false, false), (&b"b;''c"[..], true, true), (&b"d\re"[..], true, true)]);
+        assert_eq!(validate(b"a;'b;''c'\r\r'd\re'", dialect).iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![CsvErrorKind::FieldCount { expected: 2, found: 1 }]);
+        assert!(validate(&haystack, dialect).is_empty());
+        // `"` is data with `'` quotes.
+        assert_eq!(Fields::with_dialect(b"\"a;b\"", dialect.terminator(Terminator::Lf)).count(), 2);
+    }
+
+    #[test]
+    fn test_0020_01_csv_utf8_bom() {
+        use bufchr::csv::{validate, CsvDialect, Encoding, Fields};
+        // data/gdp.csv starts with a UTF-8 byte order mark.
+        let gdp: &[u8] = include_bytes!("../data/gdp.csv");
+        let mut fields = Fields::new(gdp, b',');
+        assert_eq!(fields.encoding(), Encoding::Utf8Bom);
+        let first = fields.next().unwrap();
+        assert_eq!((first.data, first.quoted, first.offset), (&b"Data Source"[..], true, 3));
+        assert!(validate(gdp, CsvDialect::default()).iter().all(|e| e.offset > 3));
+
+        assert_eq!(csv_fields(b"\xEF\xBB\xBF,a\n"), csv_fields(b",a\n"));
+        assert_eq!(csv_fields(b"\xEF\xBB\xBF\r\n\"b\"\n"), csv_fields(b"\"b\"\n"));
+        assert_eq!(csv_fields(b"\xEF\xBB\xBF"), vec![]);
+        // Not a byte order mark in the middle of a stream.
+        let data: Vec<&[u8]> = Fields::new(b"\xEF\xBB\xBFa", b',').detect_encoding(false).map(|f| f.data).collect();
+        assert_eq!(data, vec![&b"\xEF\xBB\xBFa"[..]]);
+        assert_eq!(bufchr::csv::sniff(b"\xEF\xBB\xBFid;n\n1;x\n2;y\n").delimiter, b';');
+    }
+
+    #[test]
+    fn test_0020_02_csv_utf16() {
+        use bufchr::csv::{to_utf8, validate, CsvDialect, CsvErrorKind, Encoding, Fields};
+        let text = std::str::from_utf8(HAYSTACK_ISO_3166).unwrap();
+        let mut le = vec![0xFF, 0xFE];
+        let mut be = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16().chain("\u{1F600}".encode_utf16()) {
+            le.extend_from_slice(&unit.to_le_bytes());
+            be.extend_from_slice(&unit.to_be_bytes());
+        }
+        let expected = [HAYSTACK_ISO_3166, "\u{1F600}".as_bytes()].concat();
+        for (utf16, encoding) in [(&le, Encoding::Utf16Le), (&be, Encoding::Utf16Be)] {
+            let fields = Fields::new(utf16, b',');
+            assert_eq!(fields.encoding(), encoding);
+            assert_eq!(fields.count(), 0);
+            assert_eq!(validate(utf16, CsvDialect::default())[0].kind, CsvErrorKind::UnsupportedEncoding(encoding));
+            assert_eq!(to_utf8(utf16).unwrap(), &expected[..]);
+        }
+        assert_eq!(to_utf8(HAYSTACK_ISO_3166).unwrap(), HAYSTACK_ISO_3166);
+        let odd = to_utf8(&le[..le.len() - 1]).unwrap_err();
+        assert_eq!(odd.to_string(), format!("invalid UTF-16LE at byte {}", le.len() - 2));
+        // A high surrogate without a low one.
+        let unpaired = to_utf8(&be[..be.len() - 2]).unwrap_err();
+        assert_eq!((unpaired.encoding, unpaired.offset), (Encoding::Utf16Be, be.len() - 4));
+    }
+
+    #[test]
+    fn test_0021_01_utf8_lines() {
+        let text = "Zoë\nnaïve café\n\n日本語 😀\n".repeat(20);
+        let mut bf = Bufchr::new(text.as_bytes(), b'\n').validate_utf8(true);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(end) = bf.next() {
+            lines.push(bf.get_str(start, end).unwrap());
+            start = end + 1;
+        }
+        assert_eq!(lines, text.lines().collect::<Vec<_>>());
+        assert_eq!(bf.utf8_error(), None);
+        // The text between positions is only taken with validate_utf8, and not in the middle of a character.
+        assert_eq!(Bufchr::new(text.as_bytes(), b'\n').get_str(0, 2), None);
+        assert_eq!(bf.get_str(0, 3), None);
+
+        let mut bytes = text.clone().into_bytes();
+        // The second byte of the first 😀 is now ASCII.
+        let broken = text.find('😀').unwrap();
+        bytes[broken + 1] = b'x';
+        for backend in [Backend::Avx2, Backend::Sse2, Backend::Portable, Backend::Scalar] {
+            if let Some(bf) = Bufchr::with_backend(&bytes, b'\n', backend) {
+                let mut bf = bf.validate_utf8(true);
+                let mut start = 0;
+                let mut lines = 0;
+                while let Some(end) = bf.next() {
+                    if bf.get_str(start, end).is_none() {
+                        break;
+                    }
+                    lines += 1;
+                    start = end + 1;
+                }
+                assert_eq!((lines, bf.utf8_error()), (3, Some(broken)), "{}", backend);
+            }
+        }
+    }
+
+    #[test]
+    fn test_0021_02_csv_str_fields() {
+        use bufchr::csv::{Encoding, Fields, StrField};
+        let fields: Vec<StrField> = Fields::new(HAYSTACK_ISO_3166, b',').utf8().map(Result::unwrap).collect();
+        let expected: Vec<(&str, bool)> = Fields::new(HAYSTACK_ISO_3166, b',')
+            .map(|f| (std::str::from_utf8(f.data).unwrap(), f.last))
+            .collect();
+        assert_eq!(fields.iter().map(|f| (f.data, f.last)).collect::<Vec<_>>(), expected);
+
+        let mut fields = Fields::new("\u{FEFF}\"Zoë\",\"a\"\"b\"\r\n€,\n".as_bytes(), b',').utf8();
+        assert_eq!(fields.encoding(), Encoding::Utf8Bom);
+        let first = fields.next().unwrap().unwrap();
+        assert_eq!((first.offset, first.data, first.quoted), (3, "Zoë", true));
+        let data: Vec<&str> = fields.map(|f| f.unwrap().data).collect();
+        assert_eq!(data, vec!["a\"\"b", "€", ""]);
+
+        // An overlong encoding of '/' inside a quoted field, after a valid record.
+        let mut haystack = b"x,y\n".repeat(40);
+        haystack.extend_from_slice(b"\"ok\",\"a\xC0\xAFb\",c\n");
+        let error_offset = haystack.len() - 7;
+        let results: Vec<_> = Fields::new(&haystack, b',').utf8().collect();
+        assert_eq!(results.len(), 82);
+        assert_eq!(results[80].unwrap().data, "ok");
+        let error = results[81].unwrap_err();
+        assert_eq!((error.offset, error.to_string()), (error_offset, format!("invalid UTF-8 at byte {}", error_offset)));
+        // A sequence cut by the end of the haystack.
+        let error = Fields::new("a,é".as_bytes()[..3].as_ref(), b',').utf8().nth(1).unwrap().unwrap_err();
+        assert_eq!(error.offset, 2);
+    }
+
+    #[test]
+    fn test_0022_01_csv_unescaped() {
+        use std::borrow::Cow;
+        use bufchr::csv::{CsvDialect, Fields};
+        let unescape = |data: &[u8], quoted: bool, quote: u8| -> Vec<u8> {
+            let mut value = Vec::new();
+            let mut pair = false;
+            for &b in data {
+                if quoted && b == quote && pair {
+                    pair = false;
+                    continue;
+                }
+                pair = b == quote;
+                value.push(b);
+            }
+            value
+        };
+
+        // Doubled quotes at the ends of a field and across the 64 and 128 byte batches.
+        let mut haystack = Vec::new();
+        for i in 0..200 {
+            haystack.extend_from_slice(b"plain,\"");
+            haystack.extend(std::iter::repeat_n(b'x', i % 67));
+            haystack.extend_from_slice(b"\"\"in\"\"\"\"side\"\"\",\"\"\"\",\"a\"\"b\"c\n");
+        }
+        let mut fields = Fields::new(&haystack, b',').unescaped();
+        let mut expected = Fields::new(&haystack, b',');
+        let mut owned = 0;
+        while let Some(field) = fields.next() {
+            let raw = expected.next().unwrap();
+            assert_eq!((field.offset, field.quoted, field.last), (raw.offset, raw.quoted, raw.last));
+            assert_eq!(&*field.data, &unescape(raw.data, raw.quoted, b'"')[..]);
+            match field.data {
+                Cow::Borrowed(data) => assert_eq!(data, raw.data),
+                Cow::Owned(_) => owned += 1,
+            }
+            fields.recycle(field.data);
+        }
+        assert!(expected.next().is_none());
+        assert_eq!(owned, 400);
+
+        // The buffers of the fields of a record are reused by the next one.
+        let mut fields = Fields::new(b"\"a\"\"\",\"b\"\"\"\n\"c\"\"\",\"d\"\"\"\n", b',').unescaped();
+        let first: Vec<_> = fields.by_ref().take(2).collect();
+        let mut buffers: Vec<*const u8> = first.iter().map(|f| f.data.as_ptr()).collect();
+        for field in first {
+            fields.recycle(field.data);
+        }
+        let second: Vec<_> = fields.collect();
+        assert_eq!(second.iter().map(|f| &*f.data).collect::<Vec<_>>(), vec![&b"c\""[..], b"d\""]);
+        let mut reused: Vec<*const u8> = second.iter().map(|f| f.data.as_ptr()).collect();
+        buffers.sort();
+        reused.sort();
+        assert_eq!(reused, buffers);
+
+        // Text after the closing quote makes the field unquoted, so its quotes are data.
+        let data: Vec<Vec<u8>> = Fields::new(b"\"a\"\"b\"c,\"\"\"\"\r\n", b',').unescaped().map(|f| f.data.into_owned()).collect();
+        assert_eq!(data, vec![b"\"a\"\"b\"c".to_vec(), b"\"".to_vec()]);
+
+        let dialect = CsvDialect::new(b';').quote(b'\'');
+        let data: Vec<Vec<u8>> = Fields::with_dialect(b"'it''s';\"\"\"\"\n", dialect).unescaped().map(|f| f.data.into_owned()).collect();
+        assert_eq!(data, vec![b"it's".to_vec(), b"\"\"\"\"".to_vec()]);
+
+        let fields: Vec<_> = Fields::new(HAYSTACK_ISO_3166, b',').unescaped().collect();
+        let expected: Vec<_> = Fields::new(HAYSTACK_ISO_3166, b',').collect();
+        assert_eq!(fields.len(), expected.len());
+        assert!(fields.iter().zip(&expected).all(|(f, e)| f.data == unescape(e.data, e.quoted, b'"')));
+    }
+
+    #[test]
+    fn test_0012_01_csv_unaligned_haystack() {
+        let line = b"a1,\"b11\",c111,d1111,e11111\n";
+        let data: Vec<u8> = line.iter().cycle().take(line.len() * 20 + 1).copied().collect();
+        for offset in 0..32 {
+            let haystack = &data[offset..];
+            let expected: Vec<usize> = (0..haystack.len())
+                .filter(|&i| haystack[i] == b',' || haystack[i] == b'\n' || haystack[i] == b'"')
+                .collect();
+            assert_eq!(BufchrCSV::new(haystack, b',').collect::<Vec<_>>(), expected);
+            if let Some(bf) = BufchrCSV::with_backend(haystack, b',', Backend::Avx2) {
+                assert_eq!(bf.collect::<Vec<_>>(), expected);
+            }
+            assert_eq!(BufchrCSV::with_backend(haystack, b',', Backend::Sse2).unwrap().collect::<Vec<_>>(), expected);
+        }
+    }
+
     #[test]
     fn test_1001_iso_3166() {
         //let needle = b',';
         let n1 = b'\n';
-        let mut bf = Bufchr::new_avx(HAYSTACK_ISO_3166, n1);
+        let mut bf = Bufchr::with_backend(HAYSTACK_ISO_3166, n1, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(18));
         assert_eq!(bf.next(), Some(30));
         let mut last_pos;
@@ -534,7 +1284,7 @@ mod tests {
     fn test_1002_iso_3166() {
         let n1 = b',';
         let n2 = b'\n';
-        let mut bf = Bufchr2::new_avx(HAYSTACK_ISO_3166, n1, n2);
+        let mut bf = Bufchr2::with_backend(HAYSTACK_ISO_3166, n1, n2, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(4));
         assert_eq!(bf.next(), Some(18));
         let mut last_pos;
@@ -552,7 +1302,7 @@ mod tests {
         let n1 = b',';
         let n2 = b' ';
         let n3 = b'\n';
-        let mut bf = Bufchr3::new_avx(HAYSTACK_ISO_3166, n1, n2, n3);
+        let mut bf = Bufchr3::with_backend(HAYSTACK_ISO_3166, n1, n2, n3, Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(4));
         assert_eq!(bf.next(), Some(12));
         assert_eq!(bf.next(), Some(18));
@@ -568,7 +1318,7 @@ mod tests {
 
     #[test]
     fn test_1004_iso_3166() {
-        let mut bf = BufchrCSV::new_avx(HAYSTACK_ISO_3166, b',');
+        let mut bf = BufchrCSV::with_backend(HAYSTACK_ISO_3166, b',', Backend::Avx2).unwrap();
         assert_eq!(bf.next(), Some(4));
         assert_eq!(bf.next(), Some(18));
         let mut last_pos;
@@ -580,4 +1330,15 @@ mod tests {
         }
         println!("last");
     }
+
+    #[test]
+    fn test_1005_iso_3166_count() {
+        assert_eq!(bufchr::count(HAYSTACK_ISO_3166, b','), Bufchr::new(HAYSTACK_ISO_3166, b',').count());
+        assert_eq!(bufchr::count2(HAYSTACK_ISO_3166, b',', b'\n'),
+            Bufchr2::new(HAYSTACK_ISO_3166, b',', b'\n').count());
+        assert_eq!(bufchr::count3(HAYSTACK_ISO_3166, b',', b' ', b'\n'),
+            Bufchr3::new(HAYSTACK_ISO_3166, b',', b' ', b'\n').count());
+        assert_eq!(bufchr::count_csv(HAYSTACK_ISO_3166, b','),
+            BufchrCSV::new(HAYSTACK_ISO_3166, b',').count());
+    }
 }
@@ -0,0 +1,369 @@
+// bufchrbin is run as a child process, with its input on stdin or in data/test.
+
+#![cfg(feature = "std")]
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const CSV: &[u8] = b"name,note\r\na,\"x, \"\"y\"\"\"\r\n\nb,\"two\nlines\"\n";
+
+    struct Output {
+        code: i32,
+        stdout: String,
+        stderr: String,
+    }
+
+    fn run(args: &[&str], stdin: &[u8]) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_bufchrbin"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        // A usage error exits before reading stdin.
+        let _ = child.stdin.take().unwrap().write_all(stdin);
+        let output = child.wait_with_output().unwrap();
+        Output {
+            code: output.status.code().unwrap(),
+            stdout: String::from_utf8(output.stdout).unwrap(),
+            stderr: String::from_utf8(output.stderr).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_3001_01_count() {
+        let output = run(&["count", "-n", ","], CSV);
+        assert_eq!((output.code, output.stdout.as_str()), (0, "4\n"));
+        // Escaped needles, a hex needle and several -n are one needle set.
+        assert_eq!(run(&["count", "-n", "'\\n'", "-n", "0x2c"], CSV).stdout, "9\n");
+        assert_eq!(run(&["count", "--needle=\\x22"], CSV).stdout, "8\n");
+        assert_eq!(run(&["count", "-n", ",", "--json", "-"], CSV).stdout, "{\"file\":\"-\",\"count\":4}\n");
+    }
+
+    #[test]
+    fn test_3001_02_count_files() {
+        let output = run(&["count", "-n", "\\n", "data/test/ISO-3166-1.csv", "data/test/test01.txt"], b"");
+        assert_eq!(output.code, 0, "{}", output.stderr);
+        let lines: Vec<&str> = output.stdout.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("\tdata/test/ISO-3166-1.csv"));
+        assert!(lines[1].ends_with("\tdata/test/test01.txt"));
+    }
+
+    #[test]
+    fn test_3001_03_positions_and_lines() {
+        assert_eq!(run(&["positions", "-n", ",\n"], b"a,b\nc,").stdout, "1\n3\n5\n");
+        assert_eq!(run(&["positions", "-n", ",", "--json"], b"a,b,").stdout, "{\"file\":\"-\",\"positions\":[1,3]}\n");
+        assert_eq!(run(&["lines"], b"ab\r\n\ncde").stdout, "1\t0\t2\n2\t4\t0\n3\t5\t3\n");
+        assert_eq!(run(&["lines", "--json"], b"ab\n").stdout, "{\"file\":\"-\",\"line\":1,\"offset\":0,\"length\":2}\n");
+    }
+
+    #[test]
+    fn test_3001_04_csv() {
+        let output = run(&["csv"], CSV);
+        assert_eq!(output.stdout, "name\tnote\na\tx, \"y\"\nb\ttwo\\nlines\n");
+        let output = run(&["csv", "--json"], CSV);
+        assert_eq!(output.stdout, "[\"name\",\"note\"]\n[\"a\",\"x, \\\"y\\\"\"]\n[\"b\",\"two\\nlines\"]\n");
+        assert_eq!(run(&["csv", "-d", ";"], b"a;b,c\n").stdout, "a\tb,c\n");
+    }
+
+    #[test]
+    fn test_3001_05_backend() {
+        // The backend is given to the searches, so it is used even when avx2 is enabled at compile time.
+        let output = run(&["count", "-n", ",", "--backend", "scalar"], CSV);
+        assert_eq!((output.code, output.stdout.as_str()), (0, "4\n"));
+        let output = run(&["bench", "-i", "1", "--json", "-b", "scalar"], CSV);
+        assert!(output.stdout.lines().all(|line| line.contains("\"backend\":\"scalar\"")), "{}", output.stdout);
+        let output = run(&["count", "-n", ",", "-b", "avx512"], CSV);
+        assert_eq!(output.code, 2);
+        assert!(output.stderr.contains("not available"));
+    }
+
+    #[test]
+    fn test_3001_06_bench() {
+        let output = run(&["bench", "-i", "1", "--json"], CSV);
+        assert_eq!(output.code, 0);
+        let modes: Vec<&str> = output.stdout.lines()
+            .map(|line| line.split("\"mode\":\"").nth(1).unwrap().split('"').next().unwrap())
+            .collect();
+        assert_eq!(modes, vec!["naive", "bufchr3", "count3", "bufchr_csv", "count_csv", "bufchr_csv_utf8", "from_utf8"]);
+        let (positions, valid) = output.stdout.lines().partition::<Vec<_>, _>(|line| !line.contains("from_utf8\""));
+        assert!(positions.iter().all(|line| line.contains("\"count\":17,")));
+        // from_utf8 counts the valid bytes.
+        assert!(valid[0].contains(&format!("\"count\":{},", CSV.len())));
+    }
+
+    #[test]
+    fn test_3001_07_usage_errors() {
+        for args in [&["frobnicate"][..], &[], &["count"], &["count", "-n", "abcd"], &["count", "-n", "\\q"],
+                     &["csv", "-d", "ab"], &["count", "-n", ",", "--bogus"], &["bench", "-i", "0"]] {
+            let output = run(args, b"");
+            assert_eq!(output.code, 2, "{:?}", args);
+            assert!(output.stderr.starts_with("bufchrbin: "), "{:?}", args);
+        }
+        let output = run(&["--help"], b"");
+        assert_eq!(output.code, 0);
+        assert!(output.stdout.starts_with("usage: bufchrbin"));
+        let output = run(&["count", "-n", ",", "/nonexistent/file.csv"], b"");
+        assert_eq!(output.code, 1);
+        assert!(output.stderr.contains("/nonexistent/file.csv"));
+    }
+
+    #[test]
+    fn test_3002_01_wc() {
+        let output = run(&["wc"], CSV);
+        assert_eq!((output.code, output.stdout.as_str()), (0, "5\t3\t2/2/2\t40\n"));
+        let output = run(&["wc", "--json", "-d", ";"], b"a;b\nc\n\n\"d\";e;f");
+        assert_eq!(output.stdout, "{\"file\":\"-\",\"bytes\":14,\"lines\":4,\"records\":3,\"fields\":{\"min\":1,\"max\":3,\"mode\":1}}\n");
+        assert_eq!(run(&["wc", "--json"], b"\r\n").stdout, "{\"file\":\"-\",\"bytes\":2,\"lines\":1,\"records\":0,\"fields\":null}\n");
+        let output = run(&["wc", "data/test/ISO-3166-1.csv", "-"], CSV);
+        assert!(output.stdout.ends_with("\ttotal\n"), "{}", output.stdout);
+    }
+
+    #[test]
+    fn test_3002_02_wc_chunk_boundaries() {
+        // Every buffer size splits a quote, an escaped quote, a \r\n or a blank line somewhere.
+        let data = b"a,\"b\"\"\r\n\",c\r\n\r\n\n\"\"\"\"\"\",x\"y\",\r\r\n,\n\"open\n,";
+        let records: Vec<usize> = {
+            let mut records = Vec::new();
+            let mut fields = 0;
+            for field in bufchr::csv::Fields::new(data, b',') {
+                fields += 1;
+                if field.last {
+                    records.push(fields);
+                    fields = 0;
+                }
+            }
+            records
+        };
+        let expected = format!("{}\t{}\t{}/{}/{}\t{}\n", data.iter().filter(|&&b| b == b'\n').count() + 1, records.len(),
+            records.iter().min().unwrap(), records.iter().max().unwrap(), 3, data.len());
+        assert_eq!(records, vec![3, 3, 2, 1]);
+        for size in 1..=data.len() + 1 {
+            let output = run(&["wc", "--buffer-size", &size.to_string()], data);
+            assert_eq!(output.stdout, expected, "buffer size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_3003_01_cut() {
+        let data = b"id;name;price;note\n1;\"a;b\";3;\"x \"\"q\"\"\"\r\n\n2;c;4\n";
+        assert_eq!(run(&["cut", "-d", ";", "-f", "2,4"], data).stdout, "name;note\n\"a;b\";\"x \"\"q\"\"\"\nc;\n");
+        assert_eq!(run(&["cut", "-d;", "-f", "price,name,1"], data).stdout, "price;name;id\n3;\"a;b\";1\n4;c;2\n");
+        assert_eq!(run(&["cut", "-d;", "-f", "3-"], data).stdout, "price;note\n3;\"x \"\"q\"\"\"\n4\n");
+        assert_eq!(run(&["cut", "-d;", "--fields=-2"], data).stdout, "id;name\n1;\"a;b\"\n2;c\n");
+        // A missing column is empty, and a lone empty field is quoted so it is not a blank line.
+        assert_eq!(run(&["cut", "-d;", "-f", "5"], data).stdout, "\"\"\n\"\"\n\"\"\n");
+        // Quotes that are no longer needed are dropped, and a field with a new delimiter is quoted.
+        assert_eq!(run(&["cut", "-f", "1,2"], b"\"a\",b;c\n").stdout, "a,b;c\n");
+        assert_eq!(run(&["cut", "-d", ";", "-f", "1,2"], b"a,b;\"c\nd\"\n").stdout, "a,b;\"c\nd\"\n");
+    }
+
+    #[test]
+    fn test_3003_02_cut_chunk_boundaries() {
+        let data = std::fs::read("data/test/ISO-3166-1.csv").unwrap();
+        let expected = run(&["cut", "-f", "Country name,1,2-"], &data);
+        assert_eq!(expected.code, 0, "{}", expected.stderr);
+        // Every output record is the selected, unescaped fields of an input record.
+        let fields = |data: &[u8]| -> Vec<Vec<Vec<u8>>> {
+            let mut records = vec![vec![]];
+            for field in bufchr::csv::Fields::new(data, b',') {
+                let mut value = field.data.to_vec();
+                if field.quoted {
+                    value = String::from_utf8(value).unwrap().replace("\"\"", "\"").into_bytes();
+                }
+                records.last_mut().unwrap().push(value);
+                if field.last {
+                    records.push(vec![]);
+                }
+            }
+            records.pop();
+            records
+        };
+        let input = fields(&data);
+        let output = fields(expected.stdout.as_bytes());
+        assert_eq!(input.len(), output.len());
+        for (input, output) in input.iter().zip(&output) {
+            assert_eq!(output[0], input[1]);
+            assert_eq!(&output[1..], &input[..]);
+        }
+        for size in [1, 2, 3, 7, 64, 100, 1000] {
+            let output = run(&["cut", "-f", "Country name,1,2-", "--buffer-size", &size.to_string()], &data);
+            assert_eq!(output.stdout, expected.stdout, "buffer size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_3003_03_cut_errors() {
+        for args in [&["cut"][..], &["cut", "-f", "0"], &["cut", "-f", "3-1"], &["cut", "-f", "1,,2"], &["cut", "-f", "nope"]] {
+            let output = run(args, b"a,b\n");
+            assert_eq!(output.code, 2, "{:?}", args);
+        }
+        assert!(run(&["cut", "-f", "nope"], b"a,b\n").stderr.contains("no column named 'nope'"));
+    }
+
+    #[test]
+    fn test_3004_01_grep() {
+        let data = b"alpha\nbeta,1\r\ngamma\n\nalphabet";
+        let output = run(&["grep", "alpha"], data);
+        assert_eq!((output.code, output.stdout.as_str()), (0, "1:0:alpha\n5:21:alphabet\n"));
+        assert_eq!(run(&["grep", "-n", ",\r"], data).stdout, "2:6:beta,1\r\n");
+        assert_eq!(run(&["grep", "-e", "-1", "-c"], b"a-1\n-1\nb\n").stdout, "2\n");
+        assert_eq!(run(&["grep", "--json", "ta"], data).stdout,
+            "{\"file\":\"-\",\"line\":2,\"offset\":6,\"match\":8,\"text\":\"beta,1\\r\"}\n");
+        let output = run(&["grep", "delta"], data);
+        assert_eq!((output.code, output.stdout.as_str()), (1, ""));
+    }
+
+    #[test]
+    fn test_3004_02_grep_chunk_boundaries() {
+        let data = std::fs::read("data/test/ISO-3166-1.csv").unwrap();
+        // Same lines as a naive search, with the line number and offset of each.
+        let mut expected = String::new();
+        let mut offset = 0;
+        for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+            if line.windows(2).any(|w| w == b"an") {
+                expected += &format!("{}:{}:{}\n", i + 1, offset, String::from_utf8(line.to_vec()).unwrap());
+            }
+            offset += line.len() + 1;
+        }
+        for size in [1, 2, 3, 7, 64, 100, 8 << 20] {
+            let output = run(&["grep", "an", "--buffer-size", &size.to_string()], &data);
+            assert_eq!(output.stdout, expected, "buffer size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_3004_03_grep_errors() {
+        for args in [&["grep"][..], &["grep", "-e", "a", "-n", "b"], &["grep", "a\\nb"]] {
+            let output = run(args, b"a\n");
+            assert_eq!(output.code, 2, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_3005_01_csv2json() {
+        let data = b"name,\"a \"\"b\"\"\",c\r\nx,\"1\n2\",\ny\n\n\tz,\\,3,4\n";
+        let output = run(&["csv2json"], data);
+        assert_eq!(output.code, 0, "{}", output.stderr);
+        // A missing column is null and a column without a header is keyed by its number after an underscore.
+        assert_eq!(output.stdout, concat!(
+            "[\n",
+            "{\"name\":\"x\",\"a \\\"b\\\"\":\"1\\n2\",\"c\":\"\"},\n",
+            "{\"name\":\"y\",\"a \\\"b\\\"\":null,\"c\":null},\n",
+            "{\"name\":\"\\tz\",\"a \\\"b\\\"\":\"\\\\\",\"c\":\"3\",\"_4\":\"4\"}\n",
+            "]\n"));
+        assert_eq!(run(&["csv2jsonl", "-d", ";"], b"k;\x01\nv\x1f;\xff\n").stdout,
+            "{\"k\":\"v\\u001f\",\"\\u0001\":\"\u{FFFD}\"}\n");
+        // A header alone or no input at all is still a JSON document.
+        assert_eq!(run(&["csv2json"], b"k\n").stdout, "[]\n");
+        assert_eq!(run(&["csv2json"], b"").stdout, "[]\n");
+        assert_eq!(run(&["csv2jsonl"], b"k\n").stdout, "");
+    }
+
+    #[test]
+    fn test_3005_02_csv2json_chunk_boundaries() {
+        let data = std::fs::read("data/test/ISO-3166-1.csv").unwrap();
+        let expected = run(&["csv2jsonl"], &data);
+        assert_eq!(expected.code, 0, "{}", expected.stderr);
+        let records = bufchr::csv::Fields::new(&data, b',').filter(|field| field.last).count();
+        assert_eq!(expected.stdout.lines().count(), records - 1);
+        assert!(expected.stdout.starts_with("{\"Code\":\"AD\",\"Country name\":\"Andorra\"}\n"), "{}", &expected.stdout[..100]);
+        for size in [1, 2, 3, 7, 64, 100, 1000] {
+            let output = run(&["csv2jsonl", "--buffer-size", &size.to_string()], &data);
+            assert_eq!(output.stdout, expected.stdout, "buffer size {}", size);
+        }
+        let array = run(&["csv2json"], &data).stdout;
+        assert_eq!(array, format!("[\n{}\n]\n", expected.stdout.trim_end().replace('\n', ",\n")));
+    }
+
+    #[test]
+    fn test_3005_03_csv2json_unique_keys() {
+        // The key of a column without a header does not take the name of a header column.
+        assert_eq!(run(&["csv2jsonl"], b"a,3,_3,_5\n1,2,3,4,5,6\n").stdout,
+            "{\"a\":\"1\",\"3\":\"2\",\"_3\":\"3\",\"_5\":\"4\",\"__5\":\"5\",\"_6\":\"6\"}\n");
+        let output = run(&["csv2json"], b"a,b,\"a\"\n1,2,3\n");
+        assert_eq!(output.code, 1);
+        assert_eq!(output.stderr, "bufchrbin: -: duplicate column name \"a\" in the header\n");
+        // Names that are the same JSON string once invalid UTF-8 is replaced.
+        assert_eq!(run(&["csv2jsonl"], b"\xff,\xfe\n1,2\n").code, 1);
+    }
+
+    #[test]
+    fn test_3006_01_lint() {
+        let data = b"a,b\n1,x\"y\n\"2\"z,3,4\r\r\n";
+        let output = run(&["lint"], data);
+        assert_eq!(output.code, 1);
+        assert_eq!(output.stdout, concat!(
+            "record 2, line 2, column 2 (byte 7): quote in an unquoted field\n",
+            "record 3, line 3, column 1 (byte 13): text after the closing quote\n",
+            "record 3, line 3, column 3 (byte 17): 3 fields, expected 2\n",
+            "record 3, line 3, column 3 (byte 18): carriage return without a line feed\n"));
+        assert_eq!(run(&["lint", "-c"], data).stdout, "4\n");
+        assert_eq!(run(&["lint", "-d;", "--json"], b"a;b\n1\n").stdout,
+            "{\"file\":\"-\",\"record\":2,\"line\":2,\"column\":2,\"offset\":5,\"error\":\"1 fields, expected 2\"}\n");
+        let output = run(&["lint", "data/test/ISO-3166-1.csv"], b"");
+        assert_eq!((output.code, output.stdout.as_str()), (0, ""));
+        assert_eq!(run(&["lint", "-c", "-", "data/test/ISO-3166-1.csv"], b"\"a").stdout, "-:1\ndata/test/ISO-3166-1.csv:0\n");
+    }
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        b"\xff\xfe".iter().copied().chain(text.encode_utf16().flat_map(|unit| unit.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn test_3007_01_csv_utf8_bom() {
+        let output = run(&["csv2jsonl", "data/gdp.csv"], b"");
+        assert!(output.stdout.starts_with("{\"Data Source\":"), "{}", &output.stdout[..40]);
+        for size in [1, 2, 3, 4, 64] {
+            let output = run(&["cut", "-f", "2,1", "--buffer-size", &size.to_string()], b"\xef\xbb\xbfa,b\n1,2\n");
+            assert_eq!(output.stdout, "b,a\n2,1\n", "buffer size {}", size);
+        }
+        assert_eq!(run(&["csv"], b"\xef\xbb\xbfa").stdout, "a\n");
+        assert_eq!(run(&["lint", "-c"], b"\xef\xbb\xbf\"a\",b\n").stdout, "0\n");
+    }
+
+    #[test]
+    fn test_3007_02_csv_utf16() {
+        let data = utf16le("name,price\n\"Öl\",3\n");
+        assert_eq!(run(&["csv2jsonl", "--buffer-size", "1"], &data).stdout, "{\"name\":\"Öl\",\"price\":\"3\"}\n");
+        // Chunks cut the code units and the surrogate pairs.
+        let pairs = utf16le("name,note\n\"🛢 Öl\",\"a\n🛢\"\n");
+        for size in [1, 2, 3, 4, 5, 64] {
+            let output = run(&["cut", "-f", "2,1", "--buffer-size", &size.to_string()], &pairs);
+            assert_eq!(output.stdout, "note,name\n\"a\n🛢\",🛢 Öl\n", "buffer size {}", size);
+        }
+        assert_eq!(run(&["csv"], &data).stdout, "name\tprice\nÖl\t3\n");
+        let output = run(&["lint"], &data);
+        assert_eq!(output.code, 1);
+        assert_eq!(output.stdout, "record 1, line 1, column 1 (byte 0): UTF-16LE is not supported, transcode it to UTF-8\n");
+        let output = run(&["cut", "-f", "1"], b"\xff\xfea\x00\x00");
+        assert_eq!(output.code, 1);
+        assert_eq!(output.stderr, "bufchrbin: -: invalid UTF-16LE at byte 4\n");
+    }
+
+    #[test]
+    fn test_3007_03_wc_encodings() {
+        // The quoted first field after a byte order mark, which is counted in the bytes only.
+        for size in [1, 2, 3, 4, 64] {
+            let output = run(&["wc", "--buffer-size", &size.to_string()], b"\xEF\xBB\xBF\"a,b\",c\n1,2\n");
+            assert_eq!(output.stdout, "2\t2\t2/2/2\t15\n", "buffer size {}", size);
+            let output = run(&["wc", "--buffer-size", &size.to_string()], &utf16le("a,\"b\nc\"\r\n1,2\n"));
+            assert_eq!(output.stdout, "3\t2\t2/2/2\t28\n", "buffer size {}", size);
+        }
+        assert_eq!(run(&["wc"], b"\xEF\xBB\xBF").stdout, "0\t0\t-\t3\n");
+        for size in [1, 2, 3, 4, 5, 64] {
+            let output = run(&["wc", "--buffer-size", &size.to_string()], &utf16le("🛢,\"b\n🛢\"\n"));
+            assert_eq!(output.stdout, "2\t1\t2/2/2\t22\n", "buffer size {}", size);
+            let output = run(&["wc", "--buffer-size", &size.to_string()], b"\xff\xfea\x00\x00");
+            assert_eq!(output.code, 1);
+            assert_eq!(output.stderr, "bufchrbin: -: invalid UTF-16LE at byte 4\n");
+            // A high surrogate without its low surrogate at the end of input.
+            let output = run(&["wc", "--buffer-size", &size.to_string()], b"\xff\xfea\x00\x3d\xd8");
+            assert_eq!(output.stderr, "bufchrbin: -: invalid UTF-16LE at byte 4\n");
+        }
+    }
+}
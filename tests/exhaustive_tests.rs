@@ -0,0 +1,219 @@
+// Every iterator and backend against a scalar oracle,
+// for every length 0..=600, every start misalignment 0..64 and several needle densities.
+// Bugs cluster at the 64/128 byte batch boundaries, so all of them are crossed.
+
+#[cfg(test)]
+mod tests {
+    use bufchr::{Backend, Bufchr, Bufchr2, Bufchr3, BufchrCSV};
+
+    const MAX_LEN: usize = 600;
+    const MISALIGNMENTS: usize = 64;
+    const N1: u8 = b',';
+    const N2: u8 = b'\n';
+    const N3: u8 = b'"';
+    const NEEDLES: [u8; 3] = [N1, N2, N3];
+    const FILLER: &[u8] = b"abcxyz0123 \r";
+
+    // Needle probability in 1/256 units: none, sparse, about one per vector, dense, only needles.
+    const DENSITIES: [u32; 5] = [0, 2, 10, 96, 256];
+
+    struct XorShift(u64);
+    impl XorShift {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u32
+        }
+    }
+
+    fn make_data(density: u32, seed: u64) -> Vec<u8> {
+        let mut rng = XorShift(seed);
+        (0..MAX_LEN + MISALIGNMENTS).map(|_| {
+            let r = rng.next();
+            if r % 256 < density {
+                NEEDLES[(r >> 8) as usize % NEEDLES.len()]
+            }
+            else {
+                FILLER[(r >> 8) as usize % FILLER.len()]
+            }
+        }).collect()
+    }
+
+    fn oracle(haystack: &[u8], needles: &[u8]) -> Vec<usize> {
+        (0..haystack.len()).filter(|&i| needles.contains(&haystack[i])).collect()
+    }
+
+    fn check<I: Iterator<Item = usize>>(iter: Option<I>, expected: &[usize], what: &str, len: usize, offset: usize) {
+        if let Some(iter) = iter {
+            assert_eq!(iter.collect::<Vec<_>>(), expected, "{} len: {} offset: {}", what, len, offset);
+        }
+    }
+
+    fn check_all(haystack: &[u8], offset: usize) {
+        let len = haystack.len();
+
+        let expected = oracle(haystack, &[N1]);
+        check(Some(Bufchr::new(haystack, N1)), &expected, "Bufchr", len, offset);
+        check(Bufchr::with_backend(haystack, N1, Backend::Avx2), &expected, "Bufchr avx2", len, offset);
+        check(Bufchr::with_backend(haystack, N1, Backend::Sse2), &expected, "Bufchr sse2", len, offset);
+        check(Bufchr::with_backend(haystack, N1, Backend::Portable), &expected, "Bufchr portable", len, offset);
+        check(Bufchr::with_backend(haystack, N1, Backend::Scalar), &expected, "Bufchr scalar", len, offset);
+        assert_eq!(bufchr::count(haystack, N1), expected.len(), "count len: {} offset: {}", len, offset);
+
+        let expected = oracle(haystack, &[N1, N2]);
+        check(Some(Bufchr2::new(haystack, N1, N2)), &expected, "Bufchr2", len, offset);
+        check(Bufchr2::with_backend(haystack, N1, N2, Backend::Avx2), &expected, "Bufchr2 avx2", len, offset);
+        check(Bufchr2::with_backend(haystack, N1, N2, Backend::Sse2), &expected, "Bufchr2 sse2", len, offset);
+        check(Bufchr2::with_backend(haystack, N1, N2, Backend::Portable), &expected, "Bufchr2 portable", len, offset);
+        check(Bufchr2::with_backend(haystack, N1, N2, Backend::Scalar), &expected, "Bufchr2 scalar", len, offset);
+        assert_eq!(bufchr::count2(haystack, N1, N2), expected.len(), "count2 len: {} offset: {}", len, offset);
+
+        let expected = oracle(haystack, &[N1, N2, N3]);
+        check(Some(Bufchr3::new(haystack, N1, N2, N3)), &expected, "Bufchr3", len, offset);
+        check(Bufchr3::with_backend(haystack, N1, N2, N3, Backend::Avx2), &expected, "Bufchr3 avx2", len, offset);
+        check(Bufchr3::with_backend(haystack, N1, N2, N3, Backend::Sse2), &expected, "Bufchr3 sse2", len, offset);
+        check(Bufchr3::with_backend(haystack, N1, N2, N3, Backend::Portable), &expected, "Bufchr3 portable", len, offset);
+        check(Bufchr3::with_backend(haystack, N1, N2, N3, Backend::Scalar), &expected, "Bufchr3 scalar", len, offset);
+        assert_eq!(bufchr::count3(haystack, N1, N2, N3), expected.len(), "count3 len: {} offset: {}", len, offset);
+
+        check(Some(BufchrCSV::new(haystack, N1)), &expected, "BufchrCSV", len, offset);
+        check(BufchrCSV::with_backend(haystack, N1, Backend::Avx2), &expected, "BufchrCSV avx2", len, offset);
+        check(BufchrCSV::with_backend(haystack, N1, Backend::Sse2), &expected, "BufchrCSV sse2", len, offset);
+        check(BufchrCSV::with_backend(haystack, N1, Backend::Portable), &expected, "BufchrCSV portable", len, offset);
+        check(BufchrCSV::with_backend(haystack, N1, Backend::Scalar), &expected, "BufchrCSV scalar", len, offset);
+        assert_eq!(bufchr::count_csv(haystack, N1), expected.len(), "count_csv len: {} offset: {}", len, offset);
+    }
+
+    fn run_density(density: u32) {
+        let data = make_data(density, 0x9E37_79B9_7F4A_7C15 ^ density as u64);
+        let mut buf = data.clone();
+        for offset in 0..MISALIGNMENTS {
+            for len in 0..=MAX_LEN {
+                check_all(&data[offset..offset + len], offset);
+                // The last byte is a needle.
+                if len > 0 {
+                    let last = offset + len - 1;
+                    let saved = buf[last];
+                    buf[last] = N1;
+                    check_all(&buf[offset..offset + len], offset);
+                    buf[last] = saved;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_2001_01_exhaustive_no_needles() {
+        run_density(DENSITIES[0]);
+    }
+
+    #[test]
+    fn test_2001_02_exhaustive_sparse() {
+        run_density(DENSITIES[1]);
+    }
+
+    #[test]
+    fn test_2001_03_exhaustive_one_per_vector() {
+        run_density(DENSITIES[2]);
+    }
+
+    #[test]
+    fn test_2001_04_exhaustive_dense() {
+        run_density(DENSITIES[3]);
+    }
+
+    #[test]
+    fn test_2001_05_exhaustive_only_needles() {
+        run_density(DENSITIES[4]);
+    }
+
+    const BACKENDS: [Backend; 4] = [Backend::Avx2, Backend::Sse2, Backend::Portable, Backend::Scalar];
+
+    // The first error of validate_utf8 and the text between positions, against core::str.
+    fn check_utf8(haystack: &[u8], what: &str) {
+        let expected = std::str::from_utf8(haystack).err().map(|err| err.valid_up_to());
+        let valid = std::str::from_utf8(&haystack[..expected.unwrap_or(haystack.len())]).unwrap();
+        let text = |start: usize, end: usize| valid.get(start..end);
+        for &backend in &BACKENDS {
+            if let Some(mut iter) = Bufchr::with_backend(haystack, N1, backend) {
+                iter = iter.validate_utf8(true);
+                let mut start = 0;
+                while let Some(pos) = iter.next() {
+                    assert_eq!(iter.get_str(start, pos), text(start, pos), "Bufchr {} {} at {}", backend, what, pos);
+                    start = pos + 1;
+                }
+                assert_eq!(iter.utf8_error(), expected, "Bufchr {} {}", backend, what);
+                assert_eq!(iter.get_str(start, haystack.len()), text(start, haystack.len()), "Bufchr {} {}", backend, what);
+            }
+            if let Some(iter) = BufchrCSV::with_backend(haystack, N1, backend) {
+                let mut iter = iter.validate_utf8(true);
+                let mut start = 0;
+                while let Some(pos) = iter.next() {
+                    assert_eq!(iter.get_str(start, pos), text(start, pos), "BufchrCSV {} {} at {}", backend, what, pos);
+                    start = pos + 1;
+                }
+                assert_eq!(iter.utf8_error(), expected, "BufchrCSV {} {}", backend, what);
+            }
+        }
+    }
+
+    #[test]
+    fn test_2002_01_utf8_sequences() {
+        // Every pair of bytes, and the edges of every class of 3 and 4 byte sequences,
+        // across a block boundary, a batch boundary and the end of a haystack.
+        let mut sequences: Vec<Vec<u8>> = Vec::new();
+        for a in 0..=255u8 {
+            for b in 0..=255u8 {
+                sequences.push(vec![a, b]);
+            }
+        }
+        let edges = [0x00, 0x7F, 0x80, 0x8F, 0x90, 0x9F, 0xA0, 0xBF, 0xC0, 0xC1, 0xC2, 0xDF, 0xE0, 0xED, 0xEF, 0xF0, 0xF4, 0xF5, 0xFF];
+        for &a in &edges[8..] {
+            for &b in &edges {
+                for &c in &edges {
+                    sequences.push(vec![a, b, c]);
+                    for &d in &[0x41, 0x80, 0xBF, 0xC2] {
+                        sequences.push(vec![a, b, c, d]);
+                    }
+                }
+            }
+        }
+        let mut haystack = vec![b'a'; 200];
+        haystack[0] = N1;
+        haystack[100] = N1;
+        for sequence in &sequences {
+            for &at in &[0, 62, 63, 64, 126, 127, 128] {
+                let mut h = haystack.clone();
+                h[at..at + sequence.len()].copy_from_slice(sequence);
+                check_utf8(&h, &format!("{:X?} at {}", sequence, at));
+            }
+            let end = 130 - sequence.len();
+            let mut h = haystack[..130].to_vec();
+            h[end..].copy_from_slice(sequence);
+            check_utf8(&h, &format!("{:X?} at the end", sequence));
+        }
+    }
+
+    #[test]
+    fn test_2002_02_utf8_text() {
+        let chars = ["a", "bc", ",", "\n", "\"", "é", "€", "😀", "ß,", "\u{10FFFF}"];
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+        for round in 0..200 {
+            let mut text = Vec::new();
+            while text.len() < MAX_LEN {
+                text.extend_from_slice(chars[rng.next() as usize % chars.len()].as_bytes());
+            }
+            // Most rounds break one byte.
+            if round % 4 != 0 {
+                let at = rng.next() as usize % text.len();
+                text[at] = rng.next() as u8;
+            }
+            for offset in 0..8 {
+                for len in (0..text.len() - offset).step_by(7) {
+                    check_utf8(&text[offset..offset + len], &format!("round {} offset {} len {}", round, offset, len));
+                }
+            }
+        }
+    }
+}